@@ -1,106 +1,527 @@
-use crate::config::Config;
+use crate::config::{expand_homedir, Config};
+use crate::events::Events;
+use crate::links::rewrite_links;
+use crate::lock::NoteLocks;
 use crate::note_entry::NoteEntry;
-use crate::prompt::{flash_warning, prompt, prompt_yesno};
+use crate::pins::Pins;
+use crate::prompt::{prompt, prompt_yesno};
 use crate::providers::provider::NotesProvider;
+use crate::status_bar::StatusBar;
+use crate::tags::{parse_tags, set_tags, FRONT_MATTER_MAX_LINES};
+use crate::templates::{format_checked, get_new_note_content};
+use crate::trash_entry::TrashEntry;
+use crate::undo::{Operation, UndoHistory};
+use crate::{SortDir, SortField};
 
-use anyhow::{Context, Result};
-use log::debug;
-use std::io::{Stdout, Write};
-use std::path::Path;
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, warn};
+use std::fs;
+use std::io::Stdout;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
 use std::rc::Rc;
-use std::time::SystemTime;
-use std::{thread, time};
-use termion::cursor;
+use std::time::{Duration, SystemTime};
 use termion::raw::RawTerminal;
 
-pub fn delete_note<T: NotesProvider>(
-    note_to_del: &Rc<NoteEntry>,
-    notes_provider: &T,
-    config: &Config,
+// Warn before touching a note that's open in someone else's editor, offering to clean up the lock
+// if whoever held it is gone. Returns whether to go ahead with `action`.
+pub fn check_lock(
+    note: &NoteEntry,
+    locks: &NoteLocks,
     stdout: &mut RawTerminal<Stdout>,
-    stdin: &std::io::Stdin,
+    events: &mut Events,
+    action: &str,
 ) -> Result<bool> {
-    let path_str = note_to_del
-        .path
-        .to_str()
-        .context("could not convert file path to string")?;
-    if path_str.is_empty() {
-        flash_warning(
-            stdout,
-            format!("empty path found for note {}", note_to_del.name),
-        )?;
-    } else if path_str.contains(config.get_default_notes_file()) {
-        flash_warning(
+    let Some(lock) = locks.get_lock(note) else {
+        return Ok(true);
+    };
+
+    if lock.is_stale() {
+        let affirmative = prompt_yesno(
             stdout,
+            events,
             format!(
-                "{}{}Cannot delete your default notes file.",
-                termion::clear::All,
-                cursor::Goto(1, 1),
+                "{} has a stale lock from {}. Remove it and {} the note? [y/N] ",
+                note.name,
+                lock.describe(),
+                action
             ),
         )?;
-    } else {
-        let affirmative = prompt_yesno(
-            stdout,
-            stdin,
-            format!("Are you sure you want to delete {}? [y/N] ", path_str),
-        )?;
-
         if affirmative {
-            notes_provider
-                .delete_note(note_to_del)
-                .context("could not delete note")?;
-            return Ok(true);
+            locks.remove(note)?;
+        }
+        return Ok(affirmative);
+    }
+
+    prompt_yesno(
+        stdout,
+        events,
+        format!(
+            "{} is locked by {}. {} it anyway? [y/N] ",
+            note.name,
+            lock.describe(),
+            action
+        ),
+    )
+}
+
+// Find a note by the name the user typed, with or without the default extension.
+pub fn find_note<T: NotesProvider>(
+    notes_provider: &T,
+    config: &Config,
+    note_name: &str,
+) -> Result<Rc<NoteEntry>> {
+    let note_path = get_new_note_path(config, note_name).ok();
+    notes_provider
+        .get_notes(&SortField::Name, &SortDir::Asc)
+        .into_iter()
+        .find(|note| note.name == note_name || note_path.as_ref() == Some(&note.path))
+        .context(format!("No note named {} exists.", note_name))
+}
+
+// Append a timestamped entry to the end of a note.
+pub fn append_to_note<T: NotesProvider>(
+    note: &NoteEntry,
+    notes_provider: &T,
+    config: &Config,
+    text: &str,
+) -> Result<()> {
+    let mut content = notes_provider.read_note(note)?;
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+
+    let timestamp =
+        format_checked(chrono::Local::now().format(config.get_append_timestamp_format()))
+            .with_context(|| {
+                format!(
+                    "append_timestamp_format '{}' isn't a valid date format",
+                    config.get_append_timestamp_format()
+                )
+            })?;
+    content.push_str(&format!(
+        "{}{}{}\n",
+        timestamp,
+        config.get_append_separator(),
+        text.trim_end()
+    ));
+
+    notes_provider.write_note(note, &content)
+}
+
+pub fn check_deletable(note: &NoteEntry) -> Result<()> {
+    if note.path.as_os_str().is_empty() {
+        bail!("empty path found for note {}", note.name);
+    } else if note.is_default {
+        bail!("Cannot delete your default notes file.");
+    }
+
+    Ok(())
+}
+
+// Move `notes` to the trash after a single confirmation. Returns whether anything was deleted.
+pub fn delete_notes<T: NotesProvider>(
+    notes: &[Rc<NoteEntry>],
+    notes_provider: &T,
+    stdout: &mut RawTerminal<Stdout>,
+    events: &mut Events,
+    status_bar: &mut StatusBar,
+) -> Result<Vec<Operation>> {
+    if notes.is_empty() {
+        return Ok(vec![]);
+    }
+    if let Some(error) = notes.iter().find_map(|note| check_deletable(note).err()) {
+        status_bar.flash(error.to_string());
+        return Ok(vec![]);
+    }
+
+    let question = match notes {
+        [note] => format!(
+            "Move {} to the trash? [y/N] ",
+            note.path
+                .to_str()
+                .context("could not convert file path to string")?
+        ),
+        _ => format!("Move {} notes to the trash? [y/N] ", notes.len()),
+    };
+    if !prompt_yesno(stdout, events, question)? {
+        return Ok(vec![]);
+    }
+
+    // The content is kept in the undo history, in case the note is purged before it's undone.
+    let mut deleted_notes = vec![];
+    let mut failures = vec![];
+    for note in notes {
+        let result = notes_provider
+            .read_note(note)
+            .and_then(|content| notes_provider.delete_note(note).map(|_| content));
+        match result {
+            Ok(content) => deleted_notes.push(Operation::Delete {
+                name: note.name.clone(),
+                content,
+            }),
+            Err(error) => failures.push((note.name.clone(), error)),
         }
     }
+    let deleted = deleted_notes.len();
+    let message = match deleted {
+        0 => None,
+        1 => Some(format!(
+            "Moved {} to the trash. Restore it from the trash view [t].",
+            describe_notes(notes, deleted)
+        )),
+        _ => Some(format!(
+            "Moved {} to the trash. Restore them from the trash view [t].",
+            describe_notes(notes, deleted)
+        )),
+    };
+    flash_bulk_result(status_bar, message, failures);
+    Ok(deleted_notes)
+}
+
+// "todo.txt" for a single note, otherwise "3 notes", or "2 of 3 notes" if only `done` of them
+// worked out.
+fn describe_notes(notes: &[Rc<NoteEntry>], done: usize) -> String {
+    match notes {
+        [note] => note.name.clone(),
+        _ if done == notes.len() => format!("{} notes", notes.len()),
+        _ => format!("{} of {} notes", done, notes.len()),
+    }
+}
+
+// Flash how an action on several notes went, followed by the notes it failed for and why.
+fn flash_bulk_result(
+    status_bar: &mut StatusBar,
+    message: Option<String>,
+    failures: Vec<(String, anyhow::Error)>,
+) {
+    for (name, error) in &failures {
+        warn!("{}: {:#}", name, error);
+    }
+    let failures = failures
+        .iter()
+        .map(|(name, error)| format!("{} ({})", name, error))
+        .collect::<Vec<_>>();
 
-    Ok(false)
+    let message = match (message, failures.is_empty()) {
+        (Some(message), true) => message,
+        (Some(message), false) => format!("{} Failed: {}", message, failures.join(", ")),
+        (None, _) => format!("Failed: {}", failures.join(", ")),
+    };
+    status_bar.flash(message);
 }
 
+pub fn restore_note<T: NotesProvider>(
+    entry: &Rc<TrashEntry>,
+    notes_provider: &T,
+    status_bar: &mut StatusBar,
+) -> Result<bool> {
+    match notes_provider.restore_note(entry) {
+        Ok(()) => {
+            status_bar.flash(format!("Restored {}", entry.name));
+            Ok(true)
+        }
+        Err(error) => {
+            // Most likely a name collision, which the user can fix by renaming the other note.
+            status_bar.flash(format!("{:#}", error));
+            Ok(false)
+        }
+    }
+}
+
+pub fn purge_note<T: NotesProvider>(
+    entry: &Rc<TrashEntry>,
+    notes_provider: &T,
+    pins: &Pins,
+    history: &mut UndoHistory,
+    stdout: &mut RawTerminal<Stdout>,
+    events: &mut Events,
+    status_bar: &mut StatusBar,
+) -> Result<bool> {
+    let affirmative = prompt_yesno(
+        stdout,
+        events,
+        format!(
+            "Permanently delete {}? This cannot be undone. [y/N] ",
+            entry.name
+        ),
+    )?;
+
+    if affirmative {
+        notes_provider
+            .purge_note(entry)
+            .context("could not purge note")?;
+        unpin_purged(notes_provider, pins, &[entry]);
+        history.forget(&entry.name);
+        status_bar.flash(format!("Permanently deleted {}", entry.name));
+    }
+
+    Ok(affirmative)
+}
+
+// Purge anything that's been in the trash longer than the configured number of days.
+pub fn purge_expired_trash<T: NotesProvider>(notes_provider: &T, config: &Config) -> Result<()> {
+    let days = config.get_trash_auto_purge_days();
+    if days == 0 {
+        return Ok(());
+    }
+
+    // A note that can't be purged is left for next time rather than stopping the rest.
+    let max_age = Duration::from_secs(days * 24 * 60 * 60);
+    let mut purged = vec![];
+    for entry in notes_provider.get_trash()? {
+        let age = SystemTime::now()
+            .duration_since(entry.deleted)
+            .unwrap_or_default();
+        if age > max_age {
+            debug!("purging {} from the trash after {} days", entry.name, days);
+            match notes_provider.purge_note(&entry) {
+                Ok(()) => purged.push(entry),
+                Err(error) => warn!("could not purge {} from the trash: {:#}", entry.name, error),
+            }
+        }
+    }
+    if !purged.is_empty() {
+        let mut history = UndoHistory::load(config);
+        for entry in &purged {
+            history.forget(&entry.name);
+        }
+        let purged: Vec<&TrashEntry> = purged.iter().map(|entry| entry.as_ref()).collect();
+        unpin_purged(notes_provider, &Pins::new(config), &purged);
+    }
+
+    Ok(())
+}
+
+// Drop the pins of purged notes. A pin stays if there's still a note by that name, either back in
+// the notes or deleted again and waiting in the trash.
+fn unpin_purged<T: NotesProvider>(notes_provider: &T, pins: &Pins, purged: &[&TrashEntry]) {
+    let trash = match notes_provider.get_trash() {
+        Ok(trash) => trash,
+        Err(error) => {
+            warn!(
+                "could not read the trash to unpin purged notes: {:#}",
+                error
+            );
+            return;
+        }
+    };
+    let gone: Vec<&str> = purged
+        .iter()
+        .filter(|entry| !notes_provider.note_exists(&entry.original_path))
+        .filter(|entry| !trash.iter().any(|other| other.name == entry.name))
+        .map(|entry| entry.name.as_str())
+        .collect();
+    if !gone.is_empty() {
+        pins.remove(&gone);
+    }
+}
+
+// Ask for a name and create the note from `template`, or the default template for its extension.
+// Returns the new note, the operation to undo creating it and the line the template's {{cursor}}
+// was on, or None if the prompt was cancelled.
 pub fn create_note<T: NotesProvider>(
     notes_provider: &T,
     config: &Config,
+    folder: &Path,
     stdout: &mut RawTerminal<Stdout>,
-    stdin: &std::io::Stdin,
-) -> Result<()> {
+    events: &mut Events,
+    mut name: Option<String>,
+    template: Option<&Path>,
+) -> Result<Option<(NoteEntry, Operation, Option<usize>)>> {
+    let mut prompt_string = String::from("Enter a name for your new note file: ");
     loop {
         // Prompt in a loop, only exiting if we create a valid file.
-        let note_name = prompt(
-            stdout,
-            stdin,
-            String::from("Enter a name for your new note file: "),
-        )?;
+        let Some(note_name) = get_name(&mut name, stdout, events, &prompt_string)? else {
+            debug!("new note prompt cancelled.");
+            return Ok(None);
+        };
+
+        if note_name.is_empty() {
+            debug!("note name is empty. exiting prompt.");
+            return Ok(None);
+        }
 
-        let new_note_path = format!("{}{}", config.get_notes_directory(), note_name);
-        let new_note_path = Path::new(&new_note_path);
-        let new_note_path = match new_note_path.extension() {
-            Some(_) => new_note_path.to_path_buf(),
-            None => {
-                // Add an extension if there isn't one.
-                let mut new_note_path = new_note_path.to_path_buf();
-                new_note_path.set_extension(config.get_default_file_extension());
-                new_note_path
+        // New notes go in the folder being looked at.
+        let note_name = folder.join(&note_name).to_string_lossy().to_string();
+        let new_note_path = match get_new_note_path(config, &note_name) {
+            Ok(new_note_path) => new_note_path,
+            Err(error) => {
+                prompt_string = format!("{}. Enter a name for your new note file: ", error);
+                continue;
             }
         };
 
         let note = NoteEntry::new(new_note_path, note_name, SystemTime::now(), false, 0);
 
-        if note.name.is_empty() {
-            debug!("note name is empty. exiting prompt.");
-            return Ok(());
-        }
-
         match notes_provider.note_exists(&note.path) {
             false => {
-                notes_provider.create_note(note)?;
-                return Ok(());
+                let filled = get_new_note_content(config, &note.path, template)?;
+                let (note, operation) =
+                    create_named_note(notes_provider, config, &note.name, &filled.content)?;
+                return Ok(Some((note, operation, filled.cursor)));
             }
             true => {
-                // Check for empty entry.  Re-prompt if it is.
+                // The note already exists. Re-prompt.
                 let new_note_path = note
                     .path
                     .to_str()
                     .context("could not convert file path to string")?;
-                flash_warning(stdout, format!("note {} already exists", new_note_path))?;
+                prompt_string = format!(
+                    "Note {} already exists. Enter a name for your new note file: ",
+                    new_note_path
+                );
+            }
+        }
+    }
+}
+
+// Create a note without asking anything, e.g. for a link or a journal entry, starting it off with
+// `content`. Returns the operation to undo creating it. Checking the note doesn't exist yet is up
+// to the caller.
+pub fn create_named_note<T: NotesProvider>(
+    notes_provider: &T,
+    config: &Config,
+    name: &str,
+    content: &str,
+) -> Result<(NoteEntry, Operation)> {
+    let path = get_new_note_path(config, name)?;
+    let name = get_note_name(config, &path);
+    let note = notes_provider.create_note(NoteEntry::new(
+        path,
+        name.clone(),
+        SystemTime::now(),
+        false,
+        0,
+    ))?;
+    if !content.is_empty() {
+        notes_provider.write_note(&note, content)?;
+    }
+    Ok((
+        note,
+        Operation::Create {
+            name,
+            content: None,
+        },
+    ))
+}
+
+// Rename or move a note, taking its pin along if it has one.
+pub fn rename_keeping_pin<T: NotesProvider>(
+    notes_provider: &T,
+    config: &Config,
+    note: &NoteEntry,
+    new_path: &Path,
+) -> Result<()> {
+    notes_provider.rename_note(note, new_path)?;
+    Pins::new(config).rename(&note.name, &get_note_name(config, new_path));
+    Ok(())
+}
+
+// Use the name given up front, e.g. on the command line, the first time round. After that, or if
+// there wasn't one, ask for it.
+fn get_name(
+    name: &mut Option<String>,
+    stdout: &mut RawTerminal<Stdout>,
+    events: &mut Events,
+    prompt_string: &str,
+) -> Result<Option<String>> {
+    match name.take() {
+        Some(name) => Ok(Some(name)),
+        None => prompt(stdout, events, prompt_string.to_string()),
+    }
+}
+
+// Build the path for a note named by the user, adding the default extension if there isn't one.
+pub fn get_new_note_path(config: &Config, note_name: &str) -> Result<PathBuf> {
+    let mut new_note_path =
+        Path::new(config.get_notes_directory()).join(get_relative_path(note_name)?);
+    if new_note_path.extension().is_none() {
+        new_note_path.set_extension(config.get_default_file_extension());
+    }
+    Ok(new_note_path)
+}
+
+// A note or folder name the user typed, or a link pointed at, as a path inside the notes
+// directory. Leading slashes and `.` are dropped, and `..` is refused, since it could lead out of
+// the notes directory.
+pub fn get_relative_path(name: &str) -> Result<PathBuf> {
+    let mut relative_path = PathBuf::new();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => relative_path.push(part),
+            Component::CurDir | Component::RootDir => {}
+            Component::ParentDir | Component::Prefix(_) => {
+                bail!("{} would be outside the notes directory", name)
+            }
+        }
+    }
+    Ok(relative_path)
+}
+
+// A note's name relative to the notes directory, the way the undo history refers to it.
+fn get_note_name(config: &Config, path: &Path) -> String {
+    path.strip_prefix(config.get_notes_directory())
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+pub fn clone_note<T: NotesProvider>(
+    selected_note: &Rc<NoteEntry>,
+    notes_provider: &T,
+    config: &Config,
+    stdout: &mut RawTerminal<Stdout>,
+    events: &mut Events,
+    status_bar: &mut StatusBar,
+    mut name: Option<String>,
+) -> Result<Option<Operation>> {
+    let mut prompt_string = format!("Enter a name for the copy of '{}': ", selected_note.name);
+    loop {
+        // Prompt in a loop, only exiting if we create a valid file.
+        let Some(note_name) = get_name(&mut name, stdout, events, &prompt_string)? else {
+            debug!("clone prompt cancelled.");
+            return Ok(None);
+        };
+
+        if note_name.is_empty() {
+            debug!("note name is empty. exiting prompt.");
+            return Ok(None);
+        }
+
+        // The copy goes next to the original.
+        let note_name = selected_note.get_folder().join(&note_name);
+        let new_note_path = match get_new_note_path(config, &note_name.to_string_lossy()) {
+            Ok(new_note_path) => new_note_path,
+            Err(error) => {
+                prompt_string = format!(
+                    "{}. Enter a name for the copy of '{}': ",
+                    error, selected_note.name
+                );
+                continue;
+            }
+        };
+        match notes_provider.note_exists(&new_note_path) {
+            false => {
+                let new_note = notes_provider.clone_note(selected_note, &new_note_path)?;
+                status_bar.flash(format!(
+                    "Cloned {} to {}",
+                    selected_note.name, new_note.name
+                ));
+                return Ok(Some(Operation::Create {
+                    name: get_note_name(config, &new_note_path),
+                    content: None,
+                }));
+            }
+            true => {
+                // The note already exists. Re-prompt.
+                let new_note_path_str = new_note_path
+                    .to_str()
+                    .context("could not convert file path to string")?;
+                prompt_string = format!(
+                    "Note {} already exists. Enter a name for the copy of '{}': ",
+                    new_note_path_str, selected_note.name
+                );
             }
         }
     }
@@ -111,49 +532,60 @@ pub fn rename_note<T: NotesProvider>(
     notes_provider: &T,
     config: &Config,
     stdout: &mut RawTerminal<Stdout>,
-    stdin: &std::io::Stdin,
-) -> Result<()> {
+    events: &mut Events,
+    status_bar: &mut StatusBar,
+    mut name: Option<String>,
+) -> Result<Option<RenamedNote>> {
+    let mut prompt_string = format!("Enter a new name for '{}': ", selected_note.name);
     loop {
         // Prompt in a loop, only exiting if we create a valid file.
-        let note_name = prompt(
-            stdout,
-            stdin,
-            format!("Enter a new name for '{}': ", selected_note.name),
-        )?;
+        let Some(note_name) = get_name(&mut name, stdout, events, &prompt_string)? else {
+            debug!("rename prompt cancelled.");
+            return Ok(None);
+        };
 
         // Check for empty entry.  Re-prompt if it is.
         if note_name.is_empty() {
-            debug!("note name is empty. exiting prompt.");
-            write!(
-                stdout,
-                "{}",
-                String::from("Note name empty. Please enter a valid name.")
-            )?;
-            thread::sleep(time::Duration::from_secs(1));
+            debug!("note name is empty. re-prompting.");
+            prompt_string = format!(
+                "Note name empty. Please enter a valid name for '{}': ",
+                selected_note.name
+            );
             continue;
         }
 
-        let new_note_path = format!("{}{}", config.get_notes_directory(), note_name);
-        let new_note_path = Path::new(&new_note_path);
-        let new_note_path = match new_note_path.extension() {
-            Some(_) => new_note_path.to_path_buf(),
-            None => {
-                // Add an extension if there isn't one.
-                let mut new_note_path = new_note_path.to_path_buf();
-                new_note_path.set_extension(config.get_default_file_extension());
-                new_note_path
+        // Renaming keeps the note in its folder. Moving it elsewhere is done with move_note.
+        let new_note_name = selected_note.get_folder().join(&note_name);
+        let new_note_path = match get_new_note_path(config, &new_note_name.to_string_lossy()) {
+            Ok(new_note_path) => new_note_path,
+            Err(error) => {
+                prompt_string = format!(
+                    "{}. Please enter a valid name for '{}': ",
+                    error, selected_note.name
+                );
+                continue;
             }
         };
 
         let mut new_note = (**selected_note).clone();
+        new_note.name = get_note_name(config, &new_note_path);
+        new_note.relative_path = PathBuf::from(&new_note.name);
         new_note.path = new_note_path;
 
         match notes_provider.note_exists(&new_note.path) {
             false => {
+                // Links are found before renaming, while they still lead somewhere.
+                let relinks = find_relinks(selected_note, &new_note, notes_provider);
+
                 // Note with new path doesn't already exist, so we're good to
                 // try to rename it.
-                notes_provider.rename_note(selected_note, &new_note.path)?;
-                return Ok(());
+                rename_keeping_pin(notes_provider, config, selected_note, &new_note.path)?;
+                status_bar.flash(format!("Renamed {} to {}", selected_note.name, note_name));
+                return Ok(Some(RenamedNote {
+                    old: (**selected_note).clone(),
+                    new: new_note,
+                    relinks,
+                }));
             }
             _ => {
                 // If it failed to validate for some reason, write out the error and
@@ -162,14 +594,549 @@ pub fn rename_note<T: NotesProvider>(
                     .path
                     .to_str()
                     .context("could not convert file path to string")?;
-                flash_warning(
-                    stdout,
-                    format!(
-                        "Note {} already exists. Please enter a unique file name.",
-                        new_note_path_str
-                    ),
-                )?;
+                prompt_string = format!(
+                    "Note {} already exists. Please enter a unique file name: ",
+                    new_note_path_str
+                );
             }
         }
     }
 }
+
+// A note that was just renamed, with the links to it that can be updated by update_links.
+pub struct RenamedNote {
+    old: NoteEntry,
+    new: NoteEntry,
+    relinks: Vec<Relink>,
+}
+
+impl RenamedNote {
+    pub fn get_relative_path(&self) -> &Path {
+        &self.new.relative_path
+    }
+}
+
+// A note with links to a note being renamed, with its content before and after they're rewritten
+// and how many there are.
+struct Relink {
+    note: Rc<NoteEntry>,
+    before: String,
+    after: String,
+    links: usize,
+}
+
+// Notes that can't be read, like images or PDFs kept with the notes, can't have links in them
+// either, so they're skipped rather than stopping the rename.
+fn find_relinks<T: NotesProvider>(
+    old: &NoteEntry,
+    new: &NoteEntry,
+    notes_provider: &T,
+) -> Vec<Relink> {
+    let notes = notes_provider.get_notes(&SortField::Name, &SortDir::Asc);
+    let mut relinks = vec![];
+    for note in &notes {
+        let content = match notes_provider.read_note(note) {
+            Ok(content) => content,
+            Err(error) => {
+                warn!(
+                    "skipping {} while looking for links: {:#}",
+                    note.name, error
+                );
+                continue;
+            }
+        };
+        if let Some((after, links)) = rewrite_links(&content, old, new, &notes) {
+            relinks.push(Relink {
+                note: note.clone(),
+                before: content,
+                after,
+                links,
+            });
+        }
+    }
+    relinks
+}
+
+// Offer to point the links to a note that was just renamed at its new name. Returns the rename
+// and the link updates, to be undone together. Notes open elsewhere are only changed if the user
+// says so, and notes edited since their links were found are left alone.
+pub fn update_links<T: NotesProvider>(
+    renamed: RenamedNote,
+    notes_provider: &T,
+    locks: &NoteLocks,
+    stdout: &mut RawTerminal<Stdout>,
+    events: &mut Events,
+    status_bar: &mut StatusBar,
+) -> Result<Vec<Operation>> {
+    let RenamedNote { old, new, relinks } = renamed;
+    let mut operations = vec![Operation::Rename {
+        from: old.name.clone(),
+        to: new.name.clone(),
+    }];
+    if relinks.is_empty() {
+        return Ok(operations);
+    }
+    let links: usize = relinks.iter().map(|relink| relink.links).sum();
+    let notes: Vec<Rc<NoteEntry>> = relinks.iter().map(|relink| relink.note.clone()).collect();
+    let question = format!(
+        "Update {} {} to {} in {}? [y/N] ",
+        links,
+        if links == 1 { "link" } else { "links" },
+        new.name,
+        describe_notes(&notes, notes.len())
+    );
+    if !prompt_yesno(stdout, events, question)? {
+        return Ok(operations);
+    }
+
+    let mut failures = vec![];
+    for relink in relinks {
+        // A note linking to itself has been renamed too, and its lock was checked for that.
+        let note = match relink.note.relative_path == old.relative_path {
+            true => &new,
+            false => {
+                if !check_lock(&relink.note, locks, stdout, events, "update links in")? {
+                    continue;
+                }
+                &relink.note
+            }
+        };
+        let result = notes_provider.read_note(note).and_then(|content| {
+            if content != relink.before {
+                bail!("it has been edited since");
+            }
+            notes_provider.write_note(note, &relink.after)
+        });
+        match result {
+            Ok(()) => operations.push(Operation::Relink {
+                name: note.name.clone(),
+                before: relink.before,
+                after: relink.after,
+            }),
+            Err(error) => failures.push((note.name.clone(), error)),
+        }
+    }
+    let relinked = operations.len() - 1;
+    if relinked == 0 && failures.is_empty() {
+        return Ok(operations);
+    }
+    let message = (relinked > 0).then(|| {
+        format!(
+            "Renamed {} to {} and updated links in {}",
+            old.name,
+            new.name,
+            describe_notes(&notes, relinked)
+        )
+    });
+    flash_bulk_result(status_bar, message, failures);
+    Ok(operations)
+}
+
+pub fn move_note<T: NotesProvider>(
+    selected_note: &Rc<NoteEntry>,
+    notes_provider: &T,
+    config: &Config,
+    stdout: &mut RawTerminal<Stdout>,
+    events: &mut Events,
+    status_bar: &mut StatusBar,
+    mut name: Option<String>,
+) -> Result<Option<Operation>> {
+    let mut prompt_string = format!(
+        "Move '{}' to folder (empty for the top level): ",
+        selected_note.name
+    );
+    loop {
+        let Some(folder) = get_name(&mut name, stdout, events, &prompt_string)? else {
+            debug!("move prompt cancelled.");
+            return Ok(None);
+        };
+
+        let folder = folder.trim_matches('/');
+        let new_note_name = Path::new(folder).join(selected_note.get_file_name());
+        let new_note_path = match get_new_note_path(config, &new_note_name.to_string_lossy()) {
+            Ok(new_note_path) => new_note_path,
+            Err(error) => {
+                prompt_string = format!("{}. Move '{}' to folder: ", error, selected_note.name);
+                continue;
+            }
+        };
+        if notes_provider.note_exists(&new_note_path) {
+            prompt_string = format!(
+                "Note {} already exists. Move '{}' to folder: ",
+                new_note_name.to_string_lossy(),
+                selected_note.name
+            );
+            continue;
+        }
+
+        rename_keeping_pin(notes_provider, config, selected_note, &new_note_path)?;
+        status_bar.flash(format!(
+            "Moved {} to {}/",
+            selected_note.get_file_name(),
+            folder
+        ));
+        return Ok(Some(Operation::Rename {
+            from: selected_note.name.clone(),
+            to: get_note_name(config, &new_note_path),
+        }));
+    }
+}
+
+// Move several notes into `folder` at once, prompting for the folder if it isn't given. Notes
+// that would overwrite one already there are left where they are.
+pub fn move_notes<T: NotesProvider>(
+    notes: &[Rc<NoteEntry>],
+    notes_provider: &T,
+    config: &Config,
+    stdout: &mut RawTerminal<Stdout>,
+    events: &mut Events,
+    status_bar: &mut StatusBar,
+    mut folder: Option<String>,
+) -> Result<Vec<Operation>> {
+    if notes.is_empty() {
+        return Ok(vec![]);
+    }
+    let prompt_string = format!(
+        "Move {} to folder (empty for the top level): ",
+        describe_notes(notes, notes.len())
+    );
+    let Some(folder) = get_name(&mut folder, stdout, events, &prompt_string)? else {
+        debug!("move prompt cancelled.");
+        return Ok(vec![]);
+    };
+
+    let folder = folder.trim_matches('/');
+    let (moved_notes, failures) = move_each(notes, notes_provider, config, folder);
+    let moved = moved_notes.len();
+    let message =
+        (moved > 0).then(|| format!("Moved {} to {}/", describe_notes(notes, moved), folder));
+    flash_bulk_result(status_bar, message, failures);
+    Ok(moved_notes)
+}
+
+// Move notes into the archive folder from the config, after asking once.
+pub fn archive_notes<T: NotesProvider>(
+    notes: &[Rc<NoteEntry>],
+    notes_provider: &T,
+    config: &Config,
+    stdout: &mut RawTerminal<Stdout>,
+    events: &mut Events,
+    status_bar: &mut StatusBar,
+) -> Result<Vec<Operation>> {
+    if notes.is_empty() {
+        return Ok(vec![]);
+    }
+    let folder = config.get_archive_folder();
+    let question = format!(
+        "Archive {} to {}/? [y/N] ",
+        describe_notes(notes, notes.len()),
+        folder
+    );
+    if !prompt_yesno(stdout, events, question)? {
+        return Ok(vec![]);
+    }
+
+    let (archived_notes, failures) = move_each(notes, notes_provider, config, folder);
+    let archived = archived_notes.len();
+    let message = (archived > 0).then(|| {
+        format!(
+            "Archived {} to {}/",
+            describe_notes(notes, archived),
+            folder
+        )
+    });
+    flash_bulk_result(status_bar, message, failures);
+    Ok(archived_notes)
+}
+
+// Returns the moves that were made, and the notes that couldn't be moved with why.
+fn move_each<T: NotesProvider>(
+    notes: &[Rc<NoteEntry>],
+    notes_provider: &T,
+    config: &Config,
+    folder: &str,
+) -> (Vec<Operation>, Vec<(String, anyhow::Error)>) {
+    let mut moved_notes = vec![];
+    let mut failures = vec![];
+    for note in notes {
+        let new_note_name = Path::new(folder).join(note.get_file_name());
+        let new_note_path = match get_new_note_path(config, &new_note_name.to_string_lossy()) {
+            Ok(new_note_path) => new_note_path,
+            Err(error) => {
+                failures.push((note.name.clone(), error));
+                continue;
+            }
+        };
+        let result = if notes_provider.note_exists(&new_note_path) {
+            Err(anyhow!(
+                "{} already exists",
+                new_note_name.to_string_lossy()
+            ))
+        } else {
+            check_deletable(note)
+                .and_then(|_| rename_keeping_pin(notes_provider, config, note, &new_note_path))
+        };
+        match result {
+            Ok(_) => moved_notes.push(Operation::Rename {
+                from: note.name.clone(),
+                to: get_note_name(config, &new_note_path),
+            }),
+            Err(error) => failures.push((note.name.clone(), error)),
+        }
+    }
+    (moved_notes, failures)
+}
+
+// Copy notes out to a directory on disk, e.g. to share them, keeping their folders. Nothing
+// already in the directory is overwritten.
+pub fn export_notes<T: NotesProvider>(
+    notes: &[Rc<NoteEntry>],
+    notes_provider: &T,
+    stdout: &mut RawTerminal<Stdout>,
+    events: &mut Events,
+    status_bar: &mut StatusBar,
+    mut directory: Option<String>,
+) -> Result<bool> {
+    if notes.is_empty() {
+        return Ok(false);
+    }
+    let prompt_string = format!(
+        "Export {} to directory: ",
+        describe_notes(notes, notes.len())
+    );
+    let Some(directory) = get_name(&mut directory, stdout, events, &prompt_string)? else {
+        debug!("export prompt cancelled.");
+        return Ok(false);
+    };
+    if directory.is_empty() {
+        return Ok(false);
+    }
+
+    let directory = PathBuf::from(expand_homedir(directory));
+    let mut failures = vec![];
+    for note in notes {
+        let export_path = directory.join(&note.relative_path);
+        let result = if export_path.exists() {
+            Err(anyhow!("{} already exists", export_path.to_string_lossy()))
+        } else {
+            export_note(note, notes_provider, &export_path)
+        };
+        if let Err(error) = result {
+            failures.push((note.name.clone(), error));
+        }
+    }
+    let exported = notes.len() - failures.len();
+    let message = (exported > 0).then(|| {
+        format!(
+            "Exported {} to {}",
+            describe_notes(notes, exported),
+            directory.to_string_lossy()
+        )
+    });
+    flash_bulk_result(status_bar, message, failures);
+    Ok(exported > 0)
+}
+
+fn export_note<T: NotesProvider>(
+    note: &NoteEntry,
+    notes_provider: &T,
+    export_path: &Path,
+) -> Result<()> {
+    if let Some(parent) = export_path.parent() {
+        fs::create_dir_all(parent).context("could not create the export directory")?;
+    }
+    fs::write(export_path, notes_provider.read_note(note)?).context("could not write the note")?;
+    Ok(())
+}
+
+// Add and remove tags in the front matter of notes. `changes` lists tags to add, optionally with
+// a leading +, and tags to remove with a leading -.
+pub fn retag_notes<T: NotesProvider>(
+    notes: &[Rc<NoteEntry>],
+    notes_provider: &T,
+    stdout: &mut RawTerminal<Stdout>,
+    events: &mut Events,
+    status_bar: &mut StatusBar,
+    mut changes: Option<String>,
+) -> Result<Vec<Operation>> {
+    if notes.is_empty() {
+        return Ok(vec![]);
+    }
+    let prompt_string = format!(
+        "Tags to add (+tag) or remove (-tag) on {}: ",
+        describe_notes(notes, notes.len())
+    );
+    let Some(changes) = get_name(&mut changes, stdout, events, &prompt_string)? else {
+        debug!("retag prompt cancelled.");
+        return Ok(vec![]);
+    };
+
+    let mut added = vec![];
+    let mut removed = vec![];
+    for change in changes.split_whitespace() {
+        match change.strip_prefix('-') {
+            Some(tag) => removed.push(tag.trim_start_matches('#').to_string()),
+            None => added.push(change.trim_start_matches(['+', '#']).to_string()),
+        }
+    }
+    added.retain(|tag| !tag.is_empty());
+    removed.retain(|tag| !tag.is_empty());
+    if added.is_empty() && removed.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut operations = vec![];
+    let mut failures = vec![];
+    for note in notes {
+        match retag_note(note, notes_provider, &added, &removed) {
+            Ok(Some(operation)) => operations.push(operation),
+            Ok(None) => {}
+            Err(error) => failures.push((note.name.clone(), error)),
+        }
+    }
+    let retagged = notes.len() - failures.len();
+    let message = (retagged > 0).then(|| format!("Retagged {}", describe_notes(notes, retagged)));
+    flash_bulk_result(status_bar, message, failures);
+    Ok(operations)
+}
+
+// Returns None if the note already had the tags being added and none of the ones being removed.
+fn retag_note<T: NotesProvider>(
+    note: &NoteEntry,
+    notes_provider: &T,
+    added: &[String],
+    removed: &[String],
+) -> Result<Option<Operation>> {
+    let content = notes_provider.read_note(note)?;
+    let lines: Vec<&str> = content.lines().take(FRONT_MATTER_MAX_LINES).collect();
+    let old_tags = parse_tags(&lines);
+
+    let mut tags: Vec<String> = old_tags
+        .iter()
+        .filter(|tag| !removed.iter().any(|other| other.eq_ignore_ascii_case(tag)))
+        .cloned()
+        .collect();
+    for tag in added {
+        if !tags.iter().any(|other| other.eq_ignore_ascii_case(tag)) {
+            tags.push(tag.clone());
+        }
+    }
+    if tags == old_tags {
+        return Ok(None);
+    }
+
+    let new_content = set_tags(&content, &tags);
+    notes_provider.write_note(note, &new_content)?;
+    Ok(Some(Operation::Retag {
+        name: note.name.clone(),
+        before: content,
+        after: new_content,
+    }))
+}
+
+pub fn create_folder<T: NotesProvider>(
+    notes_provider: &T,
+    config: &Config,
+    folder: &Path,
+    stdout: &mut RawTerminal<Stdout>,
+    events: &mut Events,
+    status_bar: &mut StatusBar,
+    mut name: Option<String>,
+) -> Result<()> {
+    let mut prompt_string = String::from("Enter a name for your new folder: ");
+    loop {
+        let Some(folder_name) = get_name(&mut name, stdout, events, &prompt_string)? else {
+            debug!("new folder prompt cancelled.");
+            return Ok(());
+        };
+
+        let folder_name = folder_name.trim_matches('/');
+        if folder_name.is_empty() {
+            debug!("folder name is empty. exiting prompt.");
+            return Ok(());
+        }
+
+        let new_folder = match get_relative_path(&folder.join(folder_name).to_string_lossy()) {
+            Ok(new_folder) => new_folder,
+            Err(error) => {
+                prompt_string = format!("{}. Enter a name for your new folder: ", error);
+                continue;
+            }
+        };
+        let exists = notes_provider
+            .get_folders()?
+            .iter()
+            .any(|existing| existing.relative_path == new_folder);
+        if exists {
+            prompt_string = format!(
+                "Folder {} already exists. Enter a name for your new folder: ",
+                new_folder.to_string_lossy()
+            );
+            continue;
+        }
+
+        let mut new_folder_path = PathBuf::from(config.get_notes_directory());
+        new_folder_path.push(&new_folder);
+        notes_provider.create_folder(&new_folder_path)?;
+        status_bar.flash(format!("Created {}/", new_folder.to_string_lossy()));
+        return Ok(());
+    }
+}
+
+pub fn edit_note<T: NotesProvider>(
+    selected_note: &Rc<NoteEntry>,
+    notes_provider: &T,
+    line_number: Option<usize>,
+    locks: &NoteLocks,
+) -> Result<()> {
+    // Hold the lock until the editor exits. If someone else already has it, the user has been
+    // warned and chose to open the note anyway.
+    let _lock = locks.acquire(selected_note)?;
+
+    // Notes kept as files are opened where they are, so the editor sees the real path. Anything
+    // else is round-tripped through a temp file.
+    if notes_provider.stores_files() {
+        return run_editor(&selected_note.path, line_number);
+    }
+
+    let suffix = match selected_note.path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => format!(".{}", ext),
+        None => String::new(),
+    };
+    let temp_file = tempfile::Builder::new()
+        .prefix("terminal_notes_")
+        .suffix(&suffix)
+        .tempfile()
+        .context("could not create temp file for editing")?;
+
+    let original_content = notes_provider.read_note(selected_note)?;
+    std::fs::write(temp_file.path(), &original_content)
+        .context("could not write note to temp file")?;
+
+    run_editor(temp_file.path(), line_number)?;
+
+    let new_content =
+        std::fs::read_to_string(temp_file.path()).context("could not read back edited note")?;
+    if new_content != original_content {
+        debug!("note {} changed, writing it back", selected_note.name);
+        notes_provider.write_note(selected_note, &new_content)?;
+    }
+
+    Ok(())
+}
+
+fn run_editor(path: &Path, line_number: Option<usize>) -> Result<()> {
+    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+    let mut command = Command::new(editor);
+    if let Some(line_number) = line_number {
+        // `+N` is understood by vi, vim, nvim, nano, emacs and most other terminal editors.
+        command.arg(format!("+{}", line_number));
+    }
+    command
+        .arg(path)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .output()
+        .context("could not launch the editor")?;
+    Ok(())
+}
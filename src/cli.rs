@@ -0,0 +1,263 @@
+use crate::actions::{
+    append_to_note, check_deletable, create_named_note, edit_note, find_note, get_new_note_path,
+    get_relative_path, rename_keeping_pin,
+};
+use crate::config::Config;
+use crate::journal::open_journal;
+use crate::lock::NoteLocks;
+use crate::note_entry::NoteEntry;
+use crate::providers::provider::NotesProvider;
+use crate::render::{Column, Columnar, Field};
+use crate::templates::get_new_note_content;
+use crate::{SortDir, SortField};
+
+use anyhow::{bail, Context, Result};
+use chrono::{Local, NaiveDate};
+use clap::Subcommand;
+use std::io::{stdin, stdout, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+// Non-interactive versions of the TUI actions, for scripts, cron jobs and editor plugins.
+#[derive(Subcommand, Debug)]
+pub enum Commands {
+    #[command(about = "List all notes")]
+    List {
+        #[arg(
+            short,
+            long,
+            default_value_t = false,
+            help = "Include size and modified time"
+        )]
+        long: bool,
+    },
+    #[command(
+        about = "Create a new note, from the default template for its extension if there is one"
+    )]
+    New {
+        name: String,
+        #[arg(
+            short,
+            long,
+            help = "Start from this template instead, from the templates directory"
+        )]
+        template: Option<PathBuf>,
+    },
+    #[command(about = "Create a folder for notes")]
+    Mkdir { name: String },
+    #[command(about = "Print the contents of a note")]
+    Cat { name: String },
+    #[command(about = "Open a note in $EDITOR")]
+    Edit {
+        name: String,
+        #[arg(
+            short,
+            long,
+            default_value_t = false,
+            help = "Open even if the note is locked"
+        )]
+        force: bool,
+    },
+    #[command(about = "Move a note to the trash")]
+    Rm {
+        name: String,
+        #[arg(
+            short,
+            long,
+            default_value_t = false,
+            help = "Delete even if the note is locked"
+        )]
+        force: bool,
+    },
+    #[command(about = "Rename a note")]
+    Mv {
+        old: String,
+        new: String,
+        #[arg(
+            short,
+            long,
+            default_value_t = false,
+            help = "Rename even if the note is locked"
+        )]
+        force: bool,
+    },
+    #[command(about = "Print the path of a note on disk")]
+    Path { name: String },
+    #[command(about = "Open today's journal entry in $EDITOR, creating it if needed")]
+    Journal {
+        #[arg(
+            short,
+            long,
+            help = "Open the entry for this date instead, e.g. 2024-01-31"
+        )]
+        date: Option<NaiveDate>,
+        #[arg(
+            short,
+            long,
+            default_value_t = false,
+            help = "Open even if the note is locked"
+        )]
+        force: bool,
+    },
+    #[command(about = "Append a timestamped entry to a note. Reads stdin if no text is given")]
+    Append {
+        text: Option<String>,
+        #[arg(
+            short,
+            long,
+            help = "Note to append to, created if it doesn't exist. Defaults to the default notes file"
+        )]
+        name: Option<String>,
+    },
+}
+
+pub fn run_command<T: NotesProvider>(
+    command: Commands,
+    notes_provider: &T,
+    config: &Config,
+) -> Result<()> {
+    let locks = NoteLocks::new(config);
+    let mut stdout = stdout();
+
+    match command {
+        Commands::List { long } => {
+            let columns = [
+                Column {
+                    field: Field::Size,
+                    name: "Size".to_string(),
+                    sort_field: Some(SortField::Size),
+                },
+                Column {
+                    field: Field::Modified,
+                    name: "Modified".to_string(),
+                    sort_field: Some(SortField::Modified),
+                },
+            ];
+            for note in notes_provider.get_notes(&SortField::Name, &SortDir::Asc) {
+                if long {
+                    writeln!(
+                        stdout,
+                        "{:>10}  {}  {}",
+                        note.get_value(&columns[0]),
+                        note.get_value(&columns[1]),
+                        note.name
+                    )?;
+                } else {
+                    writeln!(stdout, "{}", note.name)?;
+                }
+            }
+        }
+        Commands::New { name, template } => {
+            let new_note_path = get_new_note_path(config, &name)?;
+            if notes_provider.note_exists(&new_note_path) {
+                bail!("Note {} already exists.", name);
+            }
+            let filled = get_new_note_content(config, &new_note_path, template.as_deref())?;
+            create_named_note(notes_provider, config, &name, &filled.content)?;
+        }
+        Commands::Mkdir { name } => {
+            let folder_path =
+                Path::new(config.get_notes_directory()).join(get_relative_path(&name)?);
+            notes_provider.create_folder(&folder_path)?;
+        }
+        Commands::Cat { name } => {
+            let note = find_note(notes_provider, config, &name)?;
+            write!(stdout, "{}", notes_provider.read_note(&note)?)?;
+        }
+        Commands::Edit { name, force } => {
+            let note = find_note(notes_provider, config, &name)?;
+            check_unlocked(&note, &locks, force)?;
+            edit_note(&note, notes_provider, None, &locks)?;
+        }
+        Commands::Rm { name, force } => {
+            let note = find_note(notes_provider, config, &name)?;
+            check_deletable(&note)?;
+            check_unlocked(&note, &locks, force)?;
+            notes_provider
+                .delete_note(&note)
+                .context("could not delete note")?;
+            writeln!(stdout, "Moved {} to the trash.", note.name)?;
+        }
+        Commands::Mv { old, new, force } => {
+            let note = find_note(notes_provider, config, &old)?;
+            check_unlocked(&note, &locks, force)?;
+            let new_note_path = get_new_note_path(config, &new)?;
+            if notes_provider.note_exists(&new_note_path) {
+                bail!("Note {} already exists.", new);
+            }
+            rename_keeping_pin(notes_provider, config, &note, &new_note_path)?;
+        }
+        Commands::Path { name } => {
+            let note = find_note(notes_provider, config, &name)?;
+            if !notes_provider.stores_files() {
+                bail!(
+                    "Note {} isn't stored as a file, so it doesn't have a path.",
+                    note.name
+                );
+            }
+            writeln!(stdout, "{}", note.path.to_string_lossy())?;
+        }
+        Commands::Journal { date, force } => {
+            let date = date.unwrap_or_else(|| Local::now().date_naive());
+            let (note, _, cursor) = open_journal(notes_provider, config, date)?;
+            check_unlocked(&note, &locks, force)?;
+            edit_note(&note, notes_provider, cursor, &locks)?;
+        }
+        Commands::Append { text, name } => {
+            let text = match text {
+                Some(text) => text,
+                None if stdin().is_terminal() => bail!("Nothing to append."),
+                None => {
+                    let mut text = String::new();
+                    stdin()
+                        .read_to_string(&mut text)
+                        .context("error reading stdin")?;
+                    text
+                }
+            };
+
+            let note = match name {
+                Some(name) => match find_note(notes_provider, config, &name) {
+                    Ok(note) => note,
+                    Err(_) => Rc::new(notes_provider.create_note(NoteEntry::new(
+                        get_new_note_path(config, &name)?,
+                        name,
+                        SystemTime::now(),
+                        false,
+                        0,
+                    ))?),
+                },
+                None => find_note(notes_provider, config, config.get_default_notes_file())?,
+            };
+
+            // Appending is still safe since the write is atomic, but whoever has the note open
+            // will overwrite the entry if they save.
+            if let Some(lock) = locks.get_lock(&note).filter(|lock| !lock.is_stale()) {
+                eprintln!(
+                    "Warning: {} is open in an editor ({}). Saving it there will drop this entry.",
+                    note.name,
+                    lock.describe()
+                );
+            }
+
+            append_to_note(&note, notes_provider, config, &text)?;
+        }
+    }
+
+    Ok(())
+}
+
+// There's nobody to ask, so refuse to touch a locked note unless forced. Stale locks are
+// cleaned up along the way.
+fn check_unlocked(note: &NoteEntry, locks: &NoteLocks, force: bool) -> Result<()> {
+    match locks.get_lock(note) {
+        Some(lock) if lock.is_stale() => locks.remove(note),
+        Some(lock) if !force => bail!(
+            "Note {} is locked by {}. Use --force to ignore the lock.",
+            note.name,
+            lock.describe()
+        ),
+        _ => Ok(()),
+    }
+}
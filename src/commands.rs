@@ -0,0 +1,513 @@
+use crate::keys::Action;
+use crate::navigation_state::{SortDir, SortField};
+
+use anyhow::{bail, Result};
+use chrono::NaiveDate;
+
+// Everything the main view can do. Key presses and lines typed at the ':' command line both end
+// up as one of these, so new features only need a command to be reachable either way. Arguments
+// left out are prompted for.
+pub enum Command {
+    Quit,
+    Noop,
+    Open(Option<String>),
+    New(Option<String>),
+    Rename(Option<String>),
+    Clone(Option<String>),
+    Move(Option<String>),
+    Delete,
+    NewFolder(Option<String>),
+    ChangeFolder(String),
+    ParentFolder,
+    // Moving the selection, carried out by NavigationState::navigate.
+    Navigate(Action),
+    Sort(Option<SortField>, Option<SortDir>),
+    Filter(Option<String>),
+    ClearFilter,
+    Search(Option<String>),
+    Trash,
+    TogglePreview,
+    PreviewDown,
+    PreviewUp,
+    Help,
+    ToggleMark,
+    VisualMark,
+    ClearMarks,
+    Archive,
+    TogglePin,
+    Export(Option<String>),
+    Undo,
+    Redo,
+    TagFilter(Option<String>),
+    ClearTagFilter,
+    TagBrowser,
+    Retag(Option<String>),
+    FollowLink(Option<usize>),
+    Journal(Option<NaiveDate>),
+    JournalPrevious,
+    JournalNext,
+}
+
+// What to complete a command's argument with.
+enum Argument {
+    None,
+    Note,
+    Folder,
+    Sort,
+    Tag,
+    Journal,
+    Text,
+}
+
+// Every command, with its aliases, what its argument completes to and a description for the help.
+const COMMANDS: &[(&[&str], Argument, &str)] = &[
+    (
+        &["open", "e"],
+        Argument::Note,
+        "Open the selected note, or the one named",
+    ),
+    (&["new"], Argument::Text, "Create a note"),
+    (&["rename"], Argument::Text, "Rename the selected note"),
+    (&["clone"], Argument::Text, "Copy the selected note"),
+    (
+        &["move", "mv"],
+        Argument::Folder,
+        "Move the selected note to a folder",
+    ),
+    (
+        &["delete", "rm"],
+        Argument::None,
+        "Move the selected note to the trash",
+    ),
+    (
+        &["archive"],
+        Argument::None,
+        "Move the selected or marked notes to the archive folder",
+    ),
+    (&["pin"], Argument::None, "Pin or unpin the selected note"),
+    (
+        &["export"],
+        Argument::Text,
+        "Copy the selected or marked notes to a directory",
+    ),
+    (&["unmark"], Argument::None, "Unmark every note"),
+    (
+        &["undo", "u"],
+        Argument::None,
+        "Undo the last change to the notes",
+    ),
+    (&["redo"], Argument::None, "Redo what was undone"),
+    (
+        &["tag"],
+        Argument::Tag,
+        "Show only notes with a tag, or tags like 'work AND NOT done'",
+    ),
+    (&["notag"], Argument::None, "Clear the tag filter"),
+    (&["tags"], Argument::None, "List every tag"),
+    (
+        &["retag"],
+        Argument::Tag,
+        "Add (+tag) or remove (-tag) tags on the selected or marked notes",
+    ),
+    (
+        &["follow"],
+        Argument::Text,
+        "Go to a link in the selected note, by number or the first one in the preview",
+    ),
+    (
+        &["journal"],
+        Argument::Journal,
+        "Open today's journal entry, the one for a date (YYYY-MM-DD), or prev or next",
+    ),
+    (&["mkdir"], Argument::Text, "Create a folder"),
+    (
+        &["cd"],
+        Argument::Folder,
+        "Open a folder. '..' goes up, no folder goes to the top",
+    ),
+    (
+        &["sort"],
+        Argument::Sort,
+        "Sort by name, size or modified, then asc or desc",
+    ),
+    (
+        &["filter"],
+        Argument::Text,
+        "Filter the list by name, or by tags with tag:<expression>",
+    ),
+    (&["nofilter"], Argument::None, "Clear the filter"),
+    (
+        &["search"],
+        Argument::Text,
+        "Search the contents of every note",
+    ),
+    (&["trash"], Argument::None, "Show the trash"),
+    (&["preview"], Argument::None, "Show or hide the preview"),
+    (&["top"], Argument::None, "Go to the first note"),
+    (&["bottom"], Argument::None, "Go to the last note"),
+    (&["help"], Argument::None, "Show the help"),
+    (&["quit", "q"], Argument::None, "Quit"),
+];
+
+impl Command {
+    // Whether running the command might create, delete or rename notes. Only the commands that
+    // just look around are ruled out.
+    pub fn changes_notes(&self) -> bool {
+        !matches!(
+            self,
+            Command::Quit
+                | Command::Noop
+                | Command::ChangeFolder(_)
+                | Command::ParentFolder
+                | Command::Navigate(_)
+                | Command::Sort(_, _)
+                | Command::Filter(_)
+                | Command::ClearFilter
+                | Command::TogglePreview
+                | Command::PreviewDown
+                | Command::PreviewUp
+                | Command::Help
+                | Command::ToggleMark
+                | Command::VisualMark
+                | Command::ClearMarks
+                | Command::TogglePin
+                | Command::TagFilter(_)
+                | Command::ClearTagFilter
+        )
+    }
+}
+
+impl From<Action> for Command {
+    fn from(action: Action) -> Self {
+        match action {
+            Action::Quit => Command::Quit,
+            Action::Noop => Command::Noop,
+            Action::OpenEditor => Command::Open(None),
+            Action::Rename => Command::Rename(None),
+            Action::Delete => Command::Delete,
+            Action::New => Command::New(None),
+            Action::NavDown
+            | Action::NavUp
+            | Action::NavTop
+            | Action::NavBottom
+            | Action::HalfPageDown
+            | Action::HalfPageUp
+            | Action::PageDown
+            | Action::PageUp
+            | Action::WindowTop
+            | Action::WindowMiddle
+            | Action::WindowBottom => Command::Navigate(action),
+            Action::Sort => Command::Sort(None, None),
+            Action::Filter => Command::Filter(None),
+            Action::ClearFilter => Command::ClearFilter,
+            Action::Search => Command::Search(None),
+            Action::Trash => Command::Trash,
+            Action::Clone => Command::Clone(None),
+            Action::TogglePreview => Command::TogglePreview,
+            Action::PreviewDown => Command::PreviewDown,
+            Action::PreviewUp => Command::PreviewUp,
+            Action::Move => Command::Move(None),
+            Action::NewFolder => Command::NewFolder(None),
+            Action::ParentFolder => Command::ParentFolder,
+            Action::Help => Command::Help,
+            // Opening the command line is handled before a command is needed.
+            Action::CommandLine => Command::Noop,
+            Action::ToggleMark => Command::ToggleMark,
+            Action::VisualMark => Command::VisualMark,
+            Action::Archive => Command::Archive,
+            Action::TogglePin => Command::TogglePin,
+            Action::Export => Command::Export(None),
+            Action::Undo => Command::Undo,
+            Action::Redo => Command::Redo,
+            Action::TagFilter => Command::TagFilter(None),
+            Action::TagBrowser => Command::TagBrowser,
+            Action::Retag => Command::Retag(None),
+            Action::FollowLink => Command::FollowLink(None),
+            Action::Journal => Command::Journal(None),
+            Action::JournalPrevious => Command::JournalPrevious,
+            Action::JournalNext => Command::JournalNext,
+        }
+    }
+}
+
+// Parse a line typed at the command line, e.g. "sort size asc".
+pub fn parse(line: &str) -> Result<Command> {
+    let line = line.trim();
+    let (name, argument) = match line.split_once(' ') {
+        Some((name, argument)) => (name, Some(argument.trim().to_string())),
+        None => (line, None),
+    };
+
+    let command = match name {
+        "" => Command::Noop,
+        "open" | "e" => Command::Open(argument),
+        "new" => Command::New(argument),
+        "rename" => Command::Rename(argument),
+        "clone" => Command::Clone(argument),
+        "move" | "mv" => Command::Move(argument),
+        "delete" | "rm" => Command::Delete,
+        "archive" => Command::Archive,
+        "pin" => Command::TogglePin,
+        "export" => Command::Export(argument),
+        "unmark" => Command::ClearMarks,
+        "undo" | "u" => Command::Undo,
+        "redo" => Command::Redo,
+        "tag" => Command::TagFilter(argument),
+        "notag" => Command::ClearTagFilter,
+        "tags" => Command::TagBrowser,
+        "retag" => Command::Retag(argument),
+        "follow" => match argument.as_deref().map(str::parse::<usize>) {
+            None => Command::FollowLink(None),
+            Some(Ok(number)) if number > 0 => Command::FollowLink(Some(number)),
+            Some(_) => bail!("Links are numbered from 1"),
+        },
+        "journal" => match argument.as_deref() {
+            None => Command::Journal(None),
+            Some("prev") => Command::JournalPrevious,
+            Some("next") => Command::JournalNext,
+            Some(date) => match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+                Ok(date) => Command::Journal(Some(date)),
+                Err(_) => bail!("'{}' isn't a date like 2024-01-31, prev or next", date),
+            },
+        },
+        "mkdir" => Command::NewFolder(argument),
+        "cd" => match argument.as_deref() {
+            Some("..") => Command::ParentFolder,
+            _ => Command::ChangeFolder(argument.unwrap_or_default()),
+        },
+        "sort" => parse_sort(argument.as_deref())?,
+        "filter" => match argument
+            .as_deref()
+            .and_then(|filter| filter.strip_prefix("tag:"))
+        {
+            // An empty expression prompts for one, like :tag on its own.
+            Some(tag_filter) => Command::TagFilter(
+                Some(tag_filter.trim().to_string()).filter(|tag| !tag.is_empty()),
+            ),
+            None => Command::Filter(argument),
+        },
+        "nofilter" => Command::ClearFilter,
+        "search" => Command::Search(argument),
+        "trash" => Command::Trash,
+        "preview" => Command::TogglePreview,
+        "top" => Command::Navigate(Action::NavBottom),
+        "bottom" => Command::Navigate(Action::NavTop),
+        "help" => Command::Help,
+        "quit" | "q" => Command::Quit,
+        _ => bail!("Unknown command '{}'. See :help", name),
+    };
+    Ok(command)
+}
+
+fn parse_sort(argument: Option<&str>) -> Result<Command> {
+    let mut words = argument.unwrap_or_default().split_whitespace();
+    let sort_field = match words.next() {
+        None => None,
+        Some("name") => Some(SortField::Name),
+        Some("size") => Some(SortField::Size),
+        Some("modified") => Some(SortField::Modified),
+        Some(field) => bail!("Can't sort by '{}'. Try name, size or modified", field),
+    };
+    let sort_dir = match words.next() {
+        None => None,
+        Some("asc") => Some(SortDir::Asc),
+        Some("desc") => Some(SortDir::Desc),
+        Some(dir) => bail!("Unknown sort direction '{}'. Try asc or desc", dir),
+    };
+    Ok(Command::Sort(sort_field, sort_dir))
+}
+
+// Possible completions of the whole line. The command name is completed first, then its argument
+// from `note_names`, `folder_names` or `tag_names` depending on the command.
+pub fn complete(
+    line: &str,
+    note_names: &[String],
+    folder_names: &[String],
+    tag_names: &[String],
+) -> Vec<String> {
+    let Some((name, argument)) = line.split_once(' ') else {
+        return COMMANDS
+            .iter()
+            .flat_map(|(names, ..)| names.iter())
+            .filter(|command| command.starts_with(line))
+            .map(|command| command.to_string())
+            .collect();
+    };
+
+    let Some((_, argument_type, _)) = COMMANDS.iter().find(|(names, ..)| names.contains(&name))
+    else {
+        return vec![];
+    };
+    let (mut completed, mut partial) = match argument.rsplit_once(' ') {
+        Some((completed, partial)) => (format!("{} {} ", name, completed), partial),
+        None => (format!("{} ", name), argument),
+    };
+    // Tags being added or removed keep their + or -.
+    if let Argument::Tag = argument_type {
+        if let Some(sign) = partial.chars().next().filter(|sign| "+-".contains(*sign)) {
+            completed.push(sign);
+            partial = &partial[1..];
+        }
+    }
+    let candidates: Vec<String> = match argument_type {
+        Argument::Note => note_names.to_vec(),
+        Argument::Folder => folder_names.to_vec(),
+        Argument::Sort if completed.split_whitespace().count() == 1 => {
+            vec!["name".into(), "size".into(), "modified".into()]
+        }
+        Argument::Sort => vec!["asc".into(), "desc".into()],
+        Argument::Tag => tag_names.to_vec(),
+        Argument::Journal => vec!["prev".into(), "next".into()],
+        Argument::None | Argument::Text => vec![],
+    };
+
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(partial))
+        .map(|candidate| format!("{}{}", completed, candidate))
+        .collect()
+}
+
+// A line per command for the help, e.g. ":move, :mv  Move the selected note to a folder".
+pub fn get_help() -> Vec<String> {
+    COMMANDS
+        .iter()
+        .map(|(names, _, description)| {
+            let names = names
+                .iter()
+                .map(|name| format!(":{}", name))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("  {:<16}{}", names, description)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(line: &str) -> String {
+        parse(line)
+            .err()
+            .expect("line should not parse")
+            .to_string()
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_commands_with_and_without_arguments() {
+        assert!(matches!(parse(""), Ok(Command::Noop)));
+        assert!(matches!(parse("  q  "), Ok(Command::Quit)));
+        assert!(matches!(parse("rename"), Ok(Command::Rename(None))));
+        assert!(
+            matches!(parse("mv  work/old "), Ok(Command::Move(Some(folder))) if folder == "work/old")
+        );
+        assert!(matches!(parse("cd .."), Ok(Command::ParentFolder)));
+        assert!(matches!(parse("cd work"), Ok(Command::ChangeFolder(folder)) if folder == "work"));
+    }
+
+    #[test]
+    fn parses_sort_fields_and_directions() {
+        assert!(matches!(
+            parse("sort size asc"),
+            Ok(Command::Sort(Some(SortField::Size), Some(SortDir::Asc)))
+        ));
+        assert!(matches!(
+            parse("sort name"),
+            Ok(Command::Sort(Some(SortField::Name), None))
+        ));
+        assert_eq!(
+            parse_error("sort colour"),
+            "Can't sort by 'colour'. Try name, size or modified"
+        );
+        assert_eq!(
+            parse_error("sort size up"),
+            "Unknown sort direction 'up'. Try asc or desc"
+        );
+    }
+
+    #[test]
+    fn unknown_commands_are_errors() {
+        assert_eq!(
+            parse_error("frobnicate"),
+            "Unknown command 'frobnicate'. See :help"
+        );
+    }
+
+    #[test]
+    fn completes_command_names() {
+        assert_eq!(complete("tr", &[], &[], &[]), names(&["trash"]));
+        assert_eq!(complete("ta", &[], &[], &[]), names(&["tag", "tags"]));
+    }
+
+    #[test]
+    fn completes_arguments_by_command() {
+        let notes = names(&["todo.md", "work/plan.md"]);
+        let folders = names(&["work", "home"]);
+        assert_eq!(
+            complete("open t", &notes, &folders, &[]),
+            names(&["open todo.md"])
+        );
+        assert_eq!(complete("mv w", &notes, &folders, &[]), names(&["mv work"]));
+        assert_eq!(
+            complete("sort size d", &notes, &folders, &[]),
+            names(&["sort size desc"])
+        );
+        assert!(complete("new t", &notes, &folders, &[]).is_empty());
+    }
+
+    #[test]
+    fn filter_tag_is_a_tag_filter() {
+        assert!(
+            matches!(parse("filter todo"), Ok(Command::Filter(Some(filter))) if filter == "todo")
+        );
+        assert!(matches!(
+            parse("filter tag:work AND NOT done"),
+            Ok(Command::TagFilter(Some(tags))) if tags == "work AND NOT done"
+        ));
+        assert!(matches!(parse("filter tag:"), Ok(Command::TagFilter(None))));
+    }
+
+    #[test]
+    fn completes_tags_keeping_their_sign() {
+        let tags = names(&["work", "oncall"]);
+        assert_eq!(
+            complete("tag work AND o", &[], &[], &tags),
+            names(&["tag work AND oncall"])
+        );
+        assert_eq!(
+            complete("retag +work -o", &[], &[], &tags),
+            names(&["retag +work -oncall"])
+        );
+    }
+
+    #[test]
+    fn parses_link_numbers() {
+        assert!(matches!(parse("follow"), Ok(Command::FollowLink(None))));
+        assert!(matches!(
+            parse("follow 2"),
+            Ok(Command::FollowLink(Some(2)))
+        ));
+        assert_eq!(parse_error("follow 0"), "Links are numbered from 1");
+    }
+
+    #[test]
+    fn parses_journal_dates() {
+        assert!(matches!(
+            parse("journal prev"),
+            Ok(Command::JournalPrevious)
+        ));
+        assert!(matches!(
+            parse("journal 2024-01-31"),
+            Ok(Command::Journal(Some(date))) if date == NaiveDate::from_ymd_opt(2024, 1, 31).unwrap()
+        ));
+        assert_eq!(
+            parse_error("journal yesterday"),
+            "'yesterday' isn't a date like 2024-01-31, prev or next"
+        );
+    }
+}
@@ -1,7 +1,10 @@
+use crate::keys::get_default_keys;
+
+use std::collections::HashMap;
 use toml::Table;
 use toml::Value;
 
-fn _expand_homedir(path: String) -> String {
+pub fn expand_homedir(path: String) -> String {
     if path.starts_with('~') {
         let home_dir =
             home::home_dir().expect("Could not evaluate home directory. That's not good.");
@@ -15,6 +18,21 @@ pub struct Config {
     notes_directory: String,
     default_notes_file: String,
     default_file_extension: String,
+    provider: String,
+    sqlite_database_path: String,
+    trash_auto_purge_days: u64,
+    lock_directory: String,
+    undo_journal: String,
+    append_timestamp_format: String,
+    append_separator: String,
+    preview_ratio: f64,
+    archive_folder: String,
+    journal_pattern: String,
+    journal_template: String,
+    pins_file: String,
+    templates_directory: String,
+    default_templates: HashMap<String, String>,
+    keys: Table,
 }
 
 impl Config {
@@ -39,10 +57,141 @@ impl Config {
             .unwrap_or(&default_file_extension)
             .as_str();
 
+        let provider = Value::String("filesystem".to_string());
+        let provider = config.get("provider").unwrap_or(&provider).as_str();
+
+        let sqlite_database_path = Value::String("~/.notes.db".to_string());
+        let sqlite_database_path = config
+            .get("sqlite_database_path")
+            .unwrap_or(&sqlite_database_path)
+            .as_str();
+
+        // Notes in the trash longer than this are purged on startup. 0, the default, keeps them
+        // forever, so nothing is deleted for good without asking for it.
+        let trash_auto_purge_days = config
+            .get("trash_auto_purge_days")
+            .and_then(|days| days.as_integer())
+            .unwrap_or(0);
+
+        // Lockfiles live next to wherever the notes are stored, so every instance sharing the
+        // notes sees them.
+        let default_lock_directory = match provider {
+            Some("sqlite") => format!("{}.locks/", sqlite_database_path.unwrap()),
+            _ => format!("{}.locks/", notes_directory.unwrap()),
+        };
+        let default_lock_directory = Value::String(default_lock_directory);
+        let lock_directory = config
+            .get("lock_directory")
+            .unwrap_or(&default_lock_directory)
+            .as_str();
+
+        // The undo history is kept next to the notes too, so it matches whatever's in them.
+        let default_undo_journal = match provider {
+            Some("sqlite") => format!("{}.undo/journal.toml", sqlite_database_path.unwrap()),
+            _ => format!("{}.undo/journal.toml", notes_directory.unwrap()),
+        };
+        let default_undo_journal = Value::String(default_undo_journal);
+        let undo_journal = config
+            .get("undo_journal")
+            .unwrap_or(&default_undo_journal)
+            .as_str();
+
+        // Pins are kept next to the notes too, in a folder the note list doesn't show.
+        let default_pins_file = match provider {
+            Some("sqlite") => format!("{}.meta/pins.toml", sqlite_database_path.unwrap()),
+            _ => format!("{}.meta/pins.toml", notes_directory.unwrap()),
+        };
+        let default_pins_file = Value::String(default_pins_file);
+        let pins_file = config
+            .get("pins_file")
+            .unwrap_or(&default_pins_file)
+            .as_str();
+
+        let append_timestamp_format = Value::String("%Y-%m-%d %H:%M".to_string());
+        let append_timestamp_format = config
+            .get("append_timestamp_format")
+            .unwrap_or(&append_timestamp_format)
+            .as_str();
+
+        let append_separator = Value::String(" - ".to_string());
+        let append_separator = config
+            .get("append_separator")
+            .unwrap_or(&append_separator)
+            .as_str();
+
+        // How much of the screen width the preview pane takes up when it's open.
+        let preview_ratio = config
+            .get("preview_ratio")
+            .and_then(|ratio| ratio.as_float())
+            .unwrap_or(0.5);
+
+        // Archived notes are moved into this folder, relative to the notes directory.
+        let archive_folder = Value::String("archive".to_string());
+        let archive_folder = config
+            .get("archive_folder")
+            .unwrap_or(&archive_folder)
+            .as_str();
+
+        // Journal entries are named by formatting their date with this, relative to the notes
+        // directory. New entries start from the template, if there is one, which is looked for in
+        // the templates directory below unless it's a full path.
+        let journal_pattern = Value::String("journal/%Y-%m-%d.md".to_string());
+        let journal_pattern = config
+            .get("journal_pattern")
+            .unwrap_or(&journal_pattern)
+            .as_str();
+        let journal_template = Value::String(String::new());
+        let journal_template = config
+            .get("journal_template")
+            .unwrap_or(&journal_template)
+            .as_str();
+
+        // New notes can start from a file in here, picked when the note is created. Notes with an
+        // extension listed in default_templates start from that one when none is picked.
+        let templates_directory = Value::String("~/.note_templates/".to_string());
+        let templates_directory = config
+            .get("templates_directory")
+            .unwrap_or(&templates_directory)
+            .as_str();
+        let default_templates = config
+            .get("default_templates")
+            .and_then(|templates| templates.as_table())
+            .map(|templates| {
+                templates
+                    .iter()
+                    .filter_map(|(extension, template)| {
+                        Some((extension.to_lowercase(), template.as_str()?.to_owned()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // Key bindings are checked when the keymap is built, so they're kept as they are here.
+        let keys = config
+            .get("keys")
+            .and_then(|keys| keys.as_table())
+            .cloned()
+            .unwrap_or_default();
+
         Config {
-            notes_directory: _expand_homedir(notes_directory.unwrap().to_owned()),
-            default_notes_file: _expand_homedir(default_notes_file.unwrap().to_owned()),
+            notes_directory: expand_homedir(notes_directory.unwrap().to_owned()),
+            default_notes_file: expand_homedir(default_notes_file.unwrap().to_owned()),
             default_file_extension: default_file_extension.unwrap().to_owned(),
+            provider: provider.unwrap().to_owned(),
+            sqlite_database_path: expand_homedir(sqlite_database_path.unwrap().to_owned()),
+            trash_auto_purge_days: trash_auto_purge_days.max(0) as u64,
+            lock_directory: expand_homedir(lock_directory.unwrap().to_owned()),
+            undo_journal: expand_homedir(undo_journal.unwrap().to_owned()),
+            pins_file: expand_homedir(pins_file.unwrap().to_owned()),
+            append_timestamp_format: append_timestamp_format.unwrap().to_owned(),
+            append_separator: append_separator.unwrap().to_owned(),
+            preview_ratio: preview_ratio.clamp(0.1, 0.9),
+            archive_folder: archive_folder.unwrap().trim_matches('/').to_owned(),
+            journal_pattern: journal_pattern.unwrap().trim_start_matches('/').to_owned(),
+            journal_template: expand_homedir(journal_template.unwrap().to_owned()),
+            templates_directory: expand_homedir(templates_directory.unwrap().to_owned()),
+            default_templates,
+            keys,
         }
     }
 
@@ -60,6 +209,37 @@ impl Config {
             String::from("default_notes_file"),
             Value::String(String::from("default_notes.txt")),
         );
+        table.insert(
+            String::from("provider"),
+            Value::String(String::from("filesystem")),
+        );
+        table.insert(
+            String::from("sqlite_database_path"),
+            Value::String(String::from("~/.notes.db")),
+        );
+        table.insert(String::from("trash_auto_purge_days"), Value::Integer(0));
+        table.insert(
+            String::from("append_timestamp_format"),
+            Value::String(String::from("%Y-%m-%d %H:%M")),
+        );
+        table.insert(
+            String::from("append_separator"),
+            Value::String(String::from(" - ")),
+        );
+        table.insert(String::from("preview_ratio"), Value::Float(0.5));
+        table.insert(
+            String::from("archive_folder"),
+            Value::String(String::from("archive")),
+        );
+        table.insert(
+            String::from("journal_pattern"),
+            Value::String(String::from("journal/%Y-%m-%d.md")),
+        );
+        table.insert(
+            String::from("templates_directory"),
+            Value::String(String::from("~/.note_templates/")),
+        );
+        table.insert(String::from("keys"), Value::Table(get_default_keys()));
 
         table
     }
@@ -79,4 +259,68 @@ impl Config {
     pub fn get_default_file_extension(&self) -> &str {
         &self.default_file_extension
     }
+
+    pub fn get_provider(&self) -> &str {
+        &self.provider
+    }
+
+    pub fn get_sqlite_database_path(&self) -> &str {
+        &self.sqlite_database_path
+    }
+
+    pub fn get_trash_auto_purge_days(&self) -> u64 {
+        self.trash_auto_purge_days
+    }
+
+    pub fn get_lock_directory(&self) -> &str {
+        &self.lock_directory
+    }
+
+    pub fn get_undo_journal(&self) -> &str {
+        &self.undo_journal
+    }
+
+    pub fn get_pins_file(&self) -> &str {
+        &self.pins_file
+    }
+
+    pub fn get_append_timestamp_format(&self) -> &str {
+        &self.append_timestamp_format
+    }
+
+    pub fn get_append_separator(&self) -> &str {
+        &self.append_separator
+    }
+
+    pub fn get_preview_ratio(&self) -> f64 {
+        self.preview_ratio
+    }
+
+    pub fn get_archive_folder(&self) -> &str {
+        &self.archive_folder
+    }
+
+    pub fn get_journal_pattern(&self) -> &str {
+        &self.journal_pattern
+    }
+
+    // Empty if there's no template.
+    pub fn get_journal_template(&self) -> &str {
+        &self.journal_template
+    }
+
+    pub fn get_templates_directory(&self) -> &str {
+        &self.templates_directory
+    }
+
+    // The template for new notes with `extension`, relative to the templates directory.
+    pub fn get_default_template(&self, extension: &str) -> Option<&str> {
+        self.default_templates
+            .get(&extension.to_lowercase())
+            .map(String::as_str)
+    }
+
+    pub fn get_keys(&self) -> &Table {
+        &self.keys
+    }
 }
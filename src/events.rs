@@ -0,0 +1,117 @@
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+use termion::event::{parse_event, Event as TermEvent, Key};
+
+pub enum Event {
+    Key(Key),
+    Resize,
+}
+
+// Reads keys from the terminal without blocking forever, so the main loop can wake up to expire
+// status messages or redraw after a resize. The tty is read unbuffered and every byte that's
+// available is parsed right away, otherwise keys could sit in a buffer that poll() doesn't know
+// about.
+pub struct Events {
+    tty: File,
+    resize_signal: UnixStream,
+    pending: VecDeque<Key>,
+}
+
+impl Events {
+    pub fn new() -> Result<Self> {
+        let tty = termion::get_tty().context("could not open the terminal for input")?;
+
+        // SIGWINCH writes a byte into this pipe, so it can be polled right alongside the tty.
+        let (resize_signal, resize_signal_write) =
+            UnixStream::pair().context("could not create resize signal pipe")?;
+        resize_signal.set_nonblocking(true)?;
+        signal_hook::low_level::pipe::register(signal_hook::consts::SIGWINCH, resize_signal_write)
+            .context("could not register resize signal handler")?;
+
+        Ok(Events {
+            tty,
+            resize_signal,
+            pending: VecDeque::new(),
+        })
+    }
+
+    // Wait for the next event. Returns None if `timeout` passes first.
+    pub fn next(&mut self, timeout: Option<Duration>) -> Result<Option<Event>> {
+        if let Some(key) = self.pending.pop_front() {
+            return Ok(Some(Event::Key(key)));
+        }
+
+        let (tty_ready, resized) = self.poll(timeout)?;
+        if resized {
+            // Several resizes may have queued up while we were busy. One redraw covers them all.
+            let mut buffer = [0u8; 64];
+            while matches!(self.resize_signal.read(&mut buffer), Ok(read) if read > 0) {}
+            debug!("terminal resized");
+            return Ok(Some(Event::Resize));
+        }
+        if !tty_ready {
+            return Ok(None);
+        }
+
+        let mut buffer = [0u8; 1024];
+        let read = self.tty.read(&mut buffer).context("could not read input")?;
+        let mut bytes = buffer[..read].iter().map(|byte| Ok(*byte));
+        while let Some(Ok(byte)) = bytes.next() {
+            // An escape at the very end of a read is the Esc key itself, not the start of an
+            // escape sequence.
+            if byte == b'\x1B' && bytes.len() == 0 {
+                self.pending.push_back(Key::Esc);
+                continue;
+            }
+
+            match parse_event(byte, &mut bytes) {
+                Ok(TermEvent::Key(key)) => self.pending.push_back(key),
+                Ok(_) => {}
+                Err(error) => warn!("error occured when processing keystroke. {}", error),
+            }
+        }
+
+        Ok(self.pending.pop_front().map(Event::Key))
+    }
+
+    // Returns whether the tty and the resize signal pipe are ready to be read.
+    fn poll(&self, timeout: Option<Duration>) -> Result<(bool, bool)> {
+        let mut fds = [
+            libc::pollfd {
+                fd: self.tty.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: self.resize_signal.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        let timeout = match timeout {
+            Some(timeout) => timeout.as_millis().min(i32::MAX as u128) as i32,
+            None => -1,
+        };
+
+        // SAFETY: fds is a valid, initialized array that outlives the call.
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+        if ready < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::Interrupted {
+                return Ok((false, false));
+            }
+            return Err(error).context("error waiting for input");
+        }
+
+        Ok((
+            fds[0].revents & libc::POLLIN != 0,
+            fds[1].revents & libc::POLLIN != 0,
+        ))
+    }
+}
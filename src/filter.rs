@@ -0,0 +1,36 @@
+// Find where `query` matches `target`, returning the matched character indices so the caller
+// can highlight them. A case-insensitive substring match is preferred, falling back to a fuzzy
+// match where the query characters only need to appear in order.
+pub fn find_matches(query: &str, target: &str) -> Option<Vec<usize>> {
+    if query.is_empty() {
+        return Some(vec![]);
+    }
+
+    let query: Vec<char> = query.to_lowercase().chars().collect();
+    let target: Vec<char> = target.to_lowercase().chars().collect();
+
+    if let Some(start) = target
+        .windows(query.len())
+        .position(|window| window == query.as_slice())
+    {
+        return Some((start..start + query.len()).collect());
+    }
+
+    let mut indices = vec![];
+    let mut query_chars = query.iter().peekable();
+    for (index, c) in target.iter().enumerate() {
+        match query_chars.peek() {
+            Some(q) if *q == c => {
+                indices.push(index);
+                query_chars.next();
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+
+    match query_chars.peek() {
+        None => Some(indices),
+        Some(_) => None,
+    }
+}
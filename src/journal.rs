@@ -0,0 +1,141 @@
+use crate::actions::{create_named_note, find_note};
+use crate::config::Config;
+use crate::note_entry::NoteEntry;
+use crate::providers::provider::NotesProvider;
+use crate::render::CalendarStrip;
+use crate::templates::{fill_template, format_date};
+use crate::undo::Operation;
+use crate::{SortDir, SortField};
+
+use anyhow::{anyhow, Result};
+use chrono::{Days, NaiveDate};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::rc::Rc;
+
+// The name of the journal entry for `date`, from the journal_pattern in the config.
+pub fn get_journal_name(config: &Config, date: NaiveDate) -> Result<String> {
+    format_date(date, config.get_journal_pattern()).ok_or_else(|| {
+        anyhow!(
+            "journal_pattern '{}' isn't a valid date format",
+            config.get_journal_pattern()
+        )
+    })
+}
+
+// The date a note is the journal entry for, or None if it isn't one. A pattern without an
+// extension gets the default one added, so the name is tried without it too.
+pub fn get_journal_date(config: &Config, note: &NoteEntry) -> Option<NaiveDate> {
+    if note.is_folder {
+        return None;
+    }
+    NaiveDate::parse_from_str(&note.name, config.get_journal_pattern())
+        .or_else(|_| {
+            NaiveDate::parse_from_str(
+                &note.relative_path.with_extension("").to_string_lossy(),
+                config.get_journal_pattern(),
+            )
+        })
+        .ok()
+}
+
+// Every journal entry, by date.
+fn get_journal_entries<T: NotesProvider>(
+    notes_provider: &T,
+    config: &Config,
+) -> BTreeMap<NaiveDate, Rc<NoteEntry>> {
+    notes_provider
+        .get_notes(&SortField::Name, &SortDir::Asc)
+        .into_iter()
+        .filter_map(|note| Some((get_journal_date(config, &note)?, note)))
+        .collect()
+}
+
+// The journal entries, kept between redraws since finding them means going through every note.
+// They're only found again once they've been cleared.
+#[derive(Default)]
+pub struct JournalEntries {
+    entries: Option<BTreeMap<NaiveDate, Rc<NoteEntry>>>,
+}
+
+impl JournalEntries {
+    pub fn get<T: NotesProvider>(
+        &mut self,
+        notes_provider: &T,
+        config: &Config,
+    ) -> &BTreeMap<NaiveDate, Rc<NoteEntry>> {
+        self.entries
+            .get_or_insert_with(|| get_journal_entries(notes_provider, config))
+    }
+
+    pub fn clear(&mut self) {
+        self.entries = None;
+    }
+}
+
+// The entry `count` entries before or after `date`. Days without an entry are skipped.
+pub fn get_adjacent_entry(
+    entries: &BTreeMap<NaiveDate, Rc<NoteEntry>>,
+    date: NaiveDate,
+    forward: bool,
+    count: usize,
+) -> Option<&Rc<NoteEntry>> {
+    let index = count.saturating_sub(1);
+    let entry = match forward {
+        true => entries.range(date.succ_opt()?..).nth(index),
+        false => entries.range(..date).nth_back(index),
+    };
+    entry.map(|(_, note)| note)
+}
+
+// The journal entry for `date`, created from the template if it doesn't exist yet. Also returns
+// the operation to undo creating it, if it was, and the line the template's {{cursor}} was on.
+pub fn open_journal<T: NotesProvider>(
+    notes_provider: &T,
+    config: &Config,
+    date: NaiveDate,
+) -> Result<(Rc<NoteEntry>, Option<Operation>, Option<usize>)> {
+    let name = get_journal_name(config, date)?;
+    if let Ok(note) = find_note(notes_provider, config, &name) {
+        return Ok((note, None, None));
+    }
+
+    let (content, cursor) = match config.get_journal_template() {
+        "" => (String::new(), None),
+        template => {
+            let template = Path::new(config.get_templates_directory()).join(template);
+            let filled = fill_template(&template, Path::new(&name), date)?;
+            (filled.content, filled.cursor)
+        }
+    };
+    let (note, operation) = create_named_note(notes_provider, config, &name, &content)?;
+    Ok((Rc::new(note), Some(operation), cursor))
+}
+
+const CALENDAR_DAYS: u64 = 14;
+
+// The days for the calendar strip in the header: two weeks up to today, or around the selected
+// entry if it's outside them.
+pub fn get_calendar_strip(
+    entries: &BTreeMap<NaiveDate, Rc<NoteEntry>>,
+    selected: Option<NaiveDate>,
+    today: NaiveDate,
+) -> CalendarStrip {
+    let first_shown = today - Days::new(CALENDAR_DAYS - 1);
+    let last = match selected {
+        Some(selected) if selected < first_shown || selected > today => selected + Days::new(3),
+        _ => today,
+    };
+    let days = (0..CALENDAR_DAYS)
+        .rev()
+        .map(|days_before| {
+            let date = last - Days::new(days_before);
+            (date, entries.contains_key(&date))
+        })
+        .collect();
+    CalendarStrip {
+        days,
+        selected,
+        today,
+    }
+}
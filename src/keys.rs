@@ -0,0 +1,725 @@
+use crate::config::Config;
+
+use anyhow::{bail, Context, Result};
+use std::time::{Duration, Instant};
+use termion::event::Key;
+use toml::{Table, Value};
+
+// The second key of a chord has to come within this long of the first.
+const CHORD_TIMEOUT: Duration = Duration::from_millis(300);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Quit,
+    Noop,
+    OpenEditor,
+    Rename,
+    Delete,
+    New,
+    NavDown,
+    NavUp,
+    NavTop,
+    NavBottom,
+    HalfPageDown,
+    HalfPageUp,
+    PageDown,
+    PageUp,
+    WindowTop,
+    WindowMiddle,
+    WindowBottom,
+    Sort,
+    Filter,
+    ClearFilter,
+    Search,
+    Trash,
+    Clone,
+    TogglePreview,
+    PreviewDown,
+    PreviewUp,
+    Move,
+    NewFolder,
+    ParentFolder,
+    Help,
+    CommandLine,
+    ToggleMark,
+    VisualMark,
+    Archive,
+    TogglePin,
+    Export,
+    Undo,
+    Redo,
+    TagFilter,
+    TagBrowser,
+    Retag,
+    FollowLink,
+    Journal,
+    JournalPrevious,
+    JournalNext,
+}
+
+// Every action that can be bound, with its name in the [keys] config table, where it's listed in
+// the help, a description and its default keys.
+const ACTIONS: &[(Action, &str, &str, &str, &[&str])] = &[
+    (
+        Action::NavDown,
+        "nav_down",
+        "Navigation",
+        "Move down",
+        &["j"],
+    ),
+    (Action::NavUp, "nav_up", "Navigation", "Move up", &["k"]),
+    (
+        Action::NavBottom,
+        "nav_bottom",
+        "Navigation",
+        "Go to the first note, or the note numbered by the count",
+        &["gg"],
+    ),
+    (
+        Action::NavTop,
+        "nav_top",
+        "Navigation",
+        "Go to the last note, or the note numbered by the count",
+        &["G"],
+    ),
+    (
+        Action::HalfPageDown,
+        "half_page_down",
+        "Navigation",
+        "Move down half a screen",
+        &["<C-d>"],
+    ),
+    (
+        Action::HalfPageUp,
+        "half_page_up",
+        "Navigation",
+        "Move up half a screen",
+        &["<C-u>"],
+    ),
+    (
+        Action::PageDown,
+        "page_down",
+        "Navigation",
+        "Move down a screen",
+        &["<C-f>"],
+    ),
+    (
+        Action::PageUp,
+        "page_up",
+        "Navigation",
+        "Move up a screen",
+        &["<C-b>"],
+    ),
+    (
+        Action::WindowTop,
+        "window_top",
+        "Navigation",
+        "Go to the top of the screen",
+        &["H"],
+    ),
+    (
+        Action::WindowMiddle,
+        "window_middle",
+        "Navigation",
+        "Go to the middle of the screen",
+        &["M"],
+    ),
+    (
+        Action::WindowBottom,
+        "window_bottom",
+        "Navigation",
+        "Go to the bottom of the screen",
+        &["L"],
+    ),
+    (
+        Action::OpenEditor,
+        "open",
+        "Notes",
+        "Open the note in $EDITOR, or open the folder",
+        &["<Enter>"],
+    ),
+    (Action::New, "new", "Notes", "Create a note", &["n"]),
+    (
+        Action::Rename,
+        "rename",
+        "Notes",
+        "Rename the note, or restore it in the trash",
+        &["r"],
+    ),
+    (Action::Clone, "clone", "Notes", "Copy the note", &["c"]),
+    (
+        Action::Move,
+        "move",
+        "Notes",
+        "Move the note to another folder",
+        &["m"],
+    ),
+    (
+        Action::Delete,
+        "delete",
+        "Notes",
+        "Move the note to the trash, or purge it in the trash",
+        &["dd"],
+    ),
+    (
+        Action::FollowLink,
+        "follow_link",
+        "Notes",
+        "Go to the first link in the preview, or the one numbered by the count",
+        &["gf"],
+    ),
+    (
+        Action::Archive,
+        "archive",
+        "Notes",
+        "Move the note to the archive folder",
+        &["a"],
+    ),
+    (
+        Action::TogglePin,
+        "toggle_pin",
+        "Notes",
+        "Pin or unpin the note. Pinned notes stay at the top of the list",
+        &["P"],
+    ),
+    (
+        Action::Export,
+        "export",
+        "Notes",
+        "Copy the note to a directory outside the notes",
+        &["E"],
+    ),
+    (
+        Action::Undo,
+        "undo",
+        "Notes",
+        "Undo the last create, rename, move, clone, delete or retag",
+        &["u"],
+    ),
+    (
+        Action::Redo,
+        "redo",
+        "Notes",
+        "Redo what was undone",
+        &["<C-r>"],
+    ),
+    (
+        Action::ToggleMark,
+        "toggle_mark",
+        "Marks",
+        "Mark or unmark the note. Note actions then act on every marked note",
+        &["<Space>"],
+    ),
+    (
+        Action::VisualMark,
+        "visual_mark",
+        "Marks",
+        "Start marking a range of notes, or finish it",
+        &["V"],
+    ),
+    (
+        Action::TagFilter,
+        "tag_filter",
+        "Tags",
+        "Show only notes with a tag, or tags like 'work AND NOT done'",
+        &["#"],
+    ),
+    (
+        Action::TagBrowser,
+        "tag_browser",
+        "Tags",
+        "List every tag with how many notes have it",
+        &["T"],
+    ),
+    (
+        Action::Retag,
+        "retag",
+        "Tags",
+        "Add (+tag) or remove (-tag) tags on the note",
+        &["+"],
+    ),
+    (
+        Action::Journal,
+        "journal",
+        "Journal",
+        "Open today's journal entry, creating it if needed",
+        &["D"],
+    ),
+    (
+        Action::JournalPrevious,
+        "journal_previous",
+        "Journal",
+        "Go to the previous journal entry",
+        &["["],
+    ),
+    (
+        Action::JournalNext,
+        "journal_next",
+        "Journal",
+        "Go to the next journal entry",
+        &["]"],
+    ),
+    (
+        Action::NewFolder,
+        "new_folder",
+        "Folders",
+        "Create a folder",
+        &["f"],
+    ),
+    (
+        Action::ParentFolder,
+        "parent_folder",
+        "Folders",
+        "Go up a folder",
+        &["h", "<Backspace>"],
+    ),
+    (
+        Action::Sort,
+        "sort",
+        "View",
+        "Sort by name [n], size [s] or modified time [m]",
+        &["s"],
+    ),
+    (
+        Action::Filter,
+        "filter",
+        "View",
+        "Filter by name as you type. Enter keeps it, Esc clears it",
+        &["/"],
+    ),
+    (
+        Action::ClearFilter,
+        "clear_filter",
+        "View",
+        "Clear the marks, the filter or the tag filter, or go back",
+        &["<Esc>"],
+    ),
+    (
+        Action::Search,
+        "search",
+        "View",
+        "Search the contents of every note",
+        &["?"],
+    ),
+    (Action::Trash, "trash", "View", "Show the trash", &["t"]),
+    (
+        Action::TogglePreview,
+        "toggle_preview",
+        "View",
+        "Show or hide the preview",
+        &["p"],
+    ),
+    (
+        Action::PreviewDown,
+        "preview_down",
+        "View",
+        "Scroll the preview down",
+        &["J"],
+    ),
+    (
+        Action::PreviewUp,
+        "preview_up",
+        "View",
+        "Scroll the preview up",
+        &["K"],
+    ),
+    (Action::Help, "help", "General", "Show this help", &["<F1>"]),
+    (
+        Action::CommandLine,
+        "command_line",
+        "General",
+        "Run a command, see below",
+        &[":"],
+    ),
+    (Action::Quit, "quit", "General", "Quit, or go back", &["q"]),
+];
+
+// Maps key sequences to actions. Sequences longer than one key are chords, like gg.
+pub struct Keymap {
+    bindings: Vec<(Vec<Key>, Action)>,
+}
+
+impl Keymap {
+    // Build the keymap from the defaults plus the [keys] table in the config. Binding an action
+    // in the config replaces all of its default keys. Fails listing every problem found, so
+    // they can all be fixed in one go.
+    pub fn new(config: &Config) -> Result<Self> {
+        let mut errors = vec![];
+        let mut bindings = vec![];
+
+        for (name, _) in config.get_keys() {
+            if !ACTIONS
+                .iter()
+                .any(|(_, action_name, ..)| action_name == name)
+            {
+                errors.push(format!("unknown action '{}'", name));
+            }
+        }
+
+        for (action, name, _, _, default_keys) in ACTIONS {
+            let sequences = match config.get_keys().get(*name) {
+                Some(Value::String(sequence)) => vec![sequence.as_str()],
+                Some(Value::Array(sequences)) => {
+                    match sequences
+                        .iter()
+                        .map(|s| s.as_str())
+                        .collect::<Option<Vec<_>>>()
+                    {
+                        Some(sequences) => sequences,
+                        None => {
+                            errors.push(format!("'{}' must be a list of strings", name));
+                            continue;
+                        }
+                    }
+                }
+                Some(_) => {
+                    errors.push(format!("'{}' must be a string or a list of strings", name));
+                    continue;
+                }
+                None => default_keys.to_vec(),
+            };
+
+            for sequence in sequences {
+                match parse_keys(sequence) {
+                    Ok(keys) => bindings.push((keys, *action)),
+                    Err(error) => errors.push(format!("{}: {}", name, error)),
+                }
+            }
+        }
+
+        // A chord can't start with a key that's bound on its own, since the chord could never be
+        // typed.
+        for (index, (keys, action)) in bindings.iter().enumerate() {
+            for (other_keys, other_action) in &bindings[index + 1..] {
+                if keys == other_keys {
+                    errors.push(format!(
+                        "'{}' is bound to both {} and {}",
+                        describe_keys(keys),
+                        get_action_name(*action),
+                        get_action_name(*other_action)
+                    ));
+                } else if keys.starts_with(other_keys) || other_keys.starts_with(keys) {
+                    let (short, long) = match keys.len() < other_keys.len() {
+                        true => ((keys, action), (other_keys, other_action)),
+                        false => ((other_keys, other_action), (keys, action)),
+                    };
+                    errors.push(format!(
+                        "'{}' ({}) blocks the chord '{}' ({})",
+                        describe_keys(short.0),
+                        get_action_name(*short.1),
+                        describe_keys(long.0),
+                        get_action_name(*long.1)
+                    ));
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            bail!("Invalid [keys] in config:\n  {}", errors.join("\n  "));
+        }
+
+        Ok(Keymap { bindings })
+    }
+
+    // Turn a key press into an action, along with the count typed before it, as in 10j. Keys
+    // that start a chord or a count are buffered in `key_buffer` until the chord is finished, or
+    // abandoned by pressing something else or taking too long.
+    pub fn handle_key(
+        &self,
+        key: Key,
+        key_buffer: &mut Vec<Key>,
+        last_keypress_time: &mut Instant,
+    ) -> (Action, Option<usize>) {
+        // A chord left unfinished for too long is dropped, but a count typed before it is kept.
+        if Instant::now().duration_since(*last_keypress_time) > CHORD_TIMEOUT {
+            let (_, keys) = self.split_count(key_buffer);
+            key_buffer.truncate(key_buffer.len() - keys.len());
+        }
+        *last_keypress_time = Instant::now();
+
+        key_buffer.push(key);
+        let (_, keys) = self.split_count(key_buffer);
+        if !keys.is_empty() && !self.starts_binding(keys) {
+            key_buffer.clear();
+            key_buffer.push(key);
+        }
+
+        let (count, keys) = self.split_count(key_buffer);
+        match self
+            .bindings
+            .iter()
+            .find(|(bound_keys, _)| bound_keys == keys)
+        {
+            Some((_, action)) => {
+                key_buffer.clear();
+                (*action, count)
+            }
+            None => {
+                if !keys.is_empty() && !self.starts_binding(keys) {
+                    key_buffer.clear();
+                }
+                (Action::Noop, None)
+            }
+        }
+    }
+
+    // Split the count off the front of `key_buffer`. Like vim, a count can't start with 0, and
+    // digits that are bound to something aren't counts either.
+    fn split_count<'a>(&self, key_buffer: &'a [Key]) -> (Option<usize>, &'a [Key]) {
+        let digits: String = key_buffer
+            .iter()
+            .map_while(|key| match key {
+                Key::Char(c) if c.is_ascii_digit() => Some(*c),
+                _ => None,
+            })
+            .collect();
+        if digits.is_empty() || digits.starts_with('0') || self.starts_binding(&key_buffer[..1]) {
+            return (None, key_buffer);
+        }
+        (digits.parse().ok(), &key_buffer[digits.len()..])
+    }
+
+    fn starts_binding(&self, key_buffer: &[Key]) -> bool {
+        self.bindings
+            .iter()
+            .any(|(keys, _)| keys.starts_with(key_buffer))
+    }
+
+    // The first key sequence bound to an action, written the way it would be in the config.
+    pub fn describe(&self, action: Action) -> Option<String> {
+        self.bindings
+            .iter()
+            .find(|(_, bound_action)| *bound_action == action)
+            .map(|(keys, _)| describe_keys(keys))
+    }
+
+    // A footer listing the keys for each of `actions`, e.g. "New file [n]; Quit [q]". Actions
+    // that aren't bound to anything are left out.
+    pub fn get_footer(&self, actions: &[(Action, &str)]) -> String {
+        actions
+            .iter()
+            .filter_map(|(action, label)| {
+                self.describe(*action)
+                    .map(|keys| format!("{} [{}]", label, keys))
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    // Every action with all of its keys and what it does, grouped by category.
+    pub fn get_help(&self) -> Vec<String> {
+        let mut help = vec![];
+        let mut last_category = "";
+        for (action, _, category, description, _) in ACTIONS {
+            if *category != last_category {
+                if !help.is_empty() {
+                    help.push(String::new());
+                }
+                help.push(category.to_string());
+                last_category = category;
+            }
+
+            let keys = self
+                .bindings
+                .iter()
+                .filter(|(_, bound_action)| bound_action == action)
+                .map(|(keys, _)| describe_keys(keys))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let keys = if keys.is_empty() {
+                String::from("(unbound)")
+            } else {
+                keys
+            };
+            help.push(format!("  {:<16}{}", keys, description));
+        }
+        help
+    }
+}
+
+// The default bindings as a [keys] table, for the generated example config.
+pub fn get_default_keys() -> Table {
+    let mut table = Table::new();
+    for (_, name, _, _, default_keys) in ACTIONS {
+        let keys = default_keys
+            .iter()
+            .map(|keys| Value::String(keys.to_string()))
+            .collect();
+        table.insert(name.to_string(), Value::Array(keys));
+    }
+    table
+}
+
+fn get_action_name(action: Action) -> &'static str {
+    ACTIONS
+        .iter()
+        .find(|(bound_action, ..)| *bound_action == action)
+        .map_or("noop", |(_, name, ..)| name)
+}
+
+// Parse a key sequence like "gg", "<C-d>" or "<Space>x". Keys without a printable character are
+// written in angle brackets.
+fn parse_keys(sequence: &str) -> Result<Vec<Key>> {
+    let mut keys = vec![];
+    let mut rest = sequence;
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(end) = rest.find('>').filter(|end| *end > 1) {
+                keys.push(parse_special_key(&rest[1..end])?);
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+        keys.push(Key::Char(c));
+        rest = &rest[c.len_utf8()..];
+    }
+
+    if keys.is_empty() {
+        bail!("empty key sequence");
+    }
+    Ok(keys)
+}
+
+fn parse_special_key(name: &str) -> Result<Key> {
+    let modified_char = || {
+        let mut chars = name[2..].chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => bail!("<{}> should be a modifier and a single character", name),
+        }
+    };
+
+    let key = match name.to_lowercase().as_str() {
+        "enter" | "cr" => Key::Char('\n'),
+        "esc" => Key::Esc,
+        "space" => Key::Char(' '),
+        "tab" => Key::Char('\t'),
+        "lt" => Key::Char('<'),
+        "backspace" | "bs" => Key::Backspace,
+        "delete" | "del" => Key::Delete,
+        "insert" => Key::Insert,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        lower if lower.starts_with("c-") => Key::Ctrl(modified_char()?.to_ascii_lowercase()),
+        lower if lower.starts_with("a-") => Key::Alt(modified_char()?),
+        lower if lower.starts_with('f') => {
+            let number = lower[1..]
+                .parse::<u8>()
+                .context(format!("unknown key <{}>", name))?;
+            Key::F(number)
+        }
+        _ => bail!("unknown key <{}>", name),
+    };
+    Ok(key)
+}
+
+fn describe_keys(keys: &[Key]) -> String {
+    keys.iter().map(describe_key).collect()
+}
+
+fn describe_key(key: &Key) -> String {
+    match key {
+        Key::Char('\n') => String::from("<Enter>"),
+        Key::Char(' ') => String::from("<Space>"),
+        Key::Char('\t') => String::from("<Tab>"),
+        Key::Char('<') => String::from("<lt>"),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("<C-{}>", c),
+        Key::Alt(c) => format!("<A-{}>", c),
+        Key::F(number) => format!("<F{}>", number),
+        Key::Esc => String::from("<Esc>"),
+        Key::Backspace => String::from("<Backspace>"),
+        Key::Delete => String::from("<Delete>"),
+        Key::Insert => String::from("<Insert>"),
+        Key::Up => String::from("<Up>"),
+        Key::Down => String::from("<Down>"),
+        Key::Left => String::from("<Left>"),
+        Key::Right => String::from("<Right>"),
+        Key::Home => String::from("<Home>"),
+        Key::End => String::from("<End>"),
+        Key::PageUp => String::from("<PageUp>"),
+        Key::PageDown => String::from("<PageDown>"),
+        _ => format!("{:?}", key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keymap(keys: &str) -> Keymap {
+        let mut config = Table::new();
+        config.insert(
+            String::from("keys"),
+            Value::Table(keys.parse().expect("keys should be valid TOML")),
+        );
+        Keymap::new(&Config::new(config)).expect("keymap should be valid")
+    }
+
+    fn chars(typed: &str) -> Vec<Key> {
+        typed.chars().map(Key::Char).collect()
+    }
+
+    #[test]
+    fn splits_a_count_off_the_front() {
+        let keymap = keymap("");
+        let keys = chars("12gg");
+        assert_eq!(keymap.split_count(&keys), (Some(12), &keys[2..]));
+        let keys = chars("3");
+        assert_eq!(keymap.split_count(&keys), (Some(3), &keys[1..]));
+    }
+
+    #[test]
+    fn keys_without_a_count_are_left_alone() {
+        let keymap = keymap("");
+        let keys = chars("gg");
+        assert_eq!(keymap.split_count(&keys), (None, &keys[..]));
+        assert_eq!(keymap.split_count(&[]), (None, &[][..]));
+    }
+
+    #[test]
+    fn a_count_cannot_start_with_zero() {
+        let keymap = keymap("");
+        let keys = chars("05j");
+        assert_eq!(keymap.split_count(&keys), (None, &keys[..]));
+    }
+
+    #[test]
+    fn bound_digits_are_not_counts() {
+        let keymap = keymap("nav_top = \"1\"");
+        let keys = chars("12j");
+        assert_eq!(keymap.split_count(&keys), (None, &keys[..]));
+    }
+
+    #[test]
+    fn counts_too_big_to_fit_are_dropped() {
+        let keymap = keymap("");
+        let keys = chars("99999999999999999999999j");
+        assert_eq!(keymap.split_count(&keys), (None, &keys[23..]));
+    }
+
+    #[test]
+    fn a_slow_count_is_kept_but_a_slow_chord_is_dropped() {
+        let keymap = keymap("");
+        let mut key_buffer = vec![];
+        let mut press_slowly = |key| {
+            let mut last_keypress_time = Instant::now() - CHORD_TIMEOUT * 2;
+            keymap.handle_key(key, &mut key_buffer, &mut last_keypress_time)
+        };
+        press_slowly(Key::Char('1'));
+        press_slowly(Key::Char('2'));
+        press_slowly(Key::Char('g'));
+        // Too late to finish the first gg, so this g starts a new one after the count.
+        assert_eq!(press_slowly(Key::Char('g')), (Action::Noop, None));
+
+        let mut last_keypress_time = Instant::now();
+        let pressed = keymap.handle_key(Key::Char('g'), &mut key_buffer, &mut last_keypress_time);
+        assert_eq!(pressed, (Action::NavBottom, Some(12)));
+    }
+}
@@ -0,0 +1,259 @@
+use crate::note_entry::NoteEntry;
+use crate::providers::provider::NotesProvider;
+
+use log::error;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+// A [[wiki link]] in a note. The target can be followed by a #heading or a |label, like
+// [[todo#today|what's next]], which don't change the note it points to.
+pub struct Link {
+    pub target: String,
+    // Counting from 0.
+    pub line: usize,
+}
+
+// Every link in `content`, in order.
+pub fn find_links(content: &str) -> Vec<Link> {
+    content
+        .lines()
+        .enumerate()
+        .flat_map(|(line_number, line)| {
+            find_link_ranges(line)
+                .into_iter()
+                .map(move |(start, end)| Link {
+                    target: get_target(&line[start..end]).to_string(),
+                    line: line_number,
+                })
+        })
+        .filter(|link| !link.target.is_empty())
+        .collect()
+}
+
+// Where the text between each [[ and ]] on a line starts and ends.
+fn find_link_ranges(line: &str) -> Vec<(usize, usize)> {
+    let mut ranges = vec![];
+    let mut offset = 0;
+    while let Some(start) = line[offset..].find("[[") {
+        let start = offset + start + 2;
+        let Some(length) = line[start..].find("]]") else {
+            break;
+        };
+        ranges.push((start, start + length));
+        offset = start + length + 2;
+    }
+    ranges
+}
+
+fn get_target(inside: &str) -> &str {
+    inside.split(['#', '|']).next().unwrap_or_default().trim()
+}
+
+// Whether `target` is the note's whole name, with or without its extension, e.g. [[work/todo]].
+fn names(target: &str, note: &NoteEntry) -> bool {
+    !note.is_folder
+        && (target == note.name || Path::new(target) == note.relative_path.with_extension(""))
+}
+
+// Links can also use just the file name without the extension, wherever the note is, e.g.
+// [[todo]].
+fn names_stem(target: &str, note: &NoteEntry) -> bool {
+    !note.is_folder
+        && !target.contains('/')
+        && note
+            .relative_path
+            .file_stem()
+            .is_some_and(|stem| stem == target)
+}
+
+// The note a link points to, out of `notes` sorted by name. A whole name wins over a file name
+// shared by notes in different folders, which goes to the first of them.
+pub fn resolve<'a>(target: &str, notes: &'a [Rc<NoteEntry>]) -> Option<&'a Rc<NoteEntry>> {
+    notes
+        .iter()
+        .find(|note| names(target, note))
+        .or_else(|| notes.iter().find(|note| names_stem(target, note)))
+}
+
+fn resolves_to(target: &str, note: &NoteEntry, notes: &[Rc<NoteEntry>]) -> bool {
+    (names(target, note) || names_stem(target, note))
+        && resolve(target, notes)
+            .is_some_and(|resolved| resolved.relative_path == note.relative_path)
+}
+
+// Point the links in `content` that go to `old` at `new` instead, written the same way they
+// were: by whole name, without the extension, or by file name. Returns the new content and how
+// many links changed, or None if none did.
+pub fn rewrite_links(
+    content: &str,
+    old: &NoteEntry,
+    new: &NoteEntry,
+    notes: &[Rc<NoteEntry>],
+) -> Option<(String, usize)> {
+    let mut rewritten = String::with_capacity(content.len());
+    let mut count = 0;
+    for line in content.split_inclusive('\n') {
+        let mut copied = 0;
+        for (start, end) in find_link_ranges(line) {
+            let inside = &line[start..end];
+            let target = get_target(inside);
+            if !resolves_to(target, old, notes) {
+                continue;
+            }
+
+            let new_target = if target == old.name {
+                new.name.clone()
+            } else if names(target, old) {
+                new.relative_path
+                    .with_extension("")
+                    .to_string_lossy()
+                    .to_string()
+            } else {
+                new.relative_path
+                    .file_stem()
+                    .map_or(new.name.clone(), |stem| stem.to_string_lossy().to_string())
+            };
+            let target_end = inside.find(['#', '|']).unwrap_or(inside.len());
+            rewritten.push_str(&line[copied..start]);
+            rewritten.push_str(&new_target);
+            rewritten.push_str(&inside[target_end..]);
+            copied = end;
+            count += 1;
+        }
+        rewritten.push_str(&line[copied..]);
+    }
+
+    (count > 0).then_some((rewritten, count))
+}
+
+// The link targets in every note, read again only when a note changes, so the backlinks of the
+// selected note can be shown without reading the whole notebook on every keypress.
+#[derive(Default)]
+pub struct LinkIndex {
+    links: HashMap<PathBuf, (SystemTime, u64, Vec<String>)>,
+}
+
+impl LinkIndex {
+    pub fn update<T: NotesProvider>(&mut self, notes: &[Rc<NoteEntry>], notes_provider: &T) {
+        let existing: HashSet<&PathBuf> = notes.iter().map(|note| &note.relative_path).collect();
+        self.links.retain(|path, _| existing.contains(path));
+
+        for note in notes {
+            let up_to_date =
+                self.links
+                    .get(&note.relative_path)
+                    .is_some_and(|(modified, size, _)| {
+                        *modified == note.modified && *size == note.size
+                    });
+            if up_to_date {
+                continue;
+            }
+            let targets = match notes_provider.read_note(note) {
+                Ok(content) => find_links(&content)
+                    .into_iter()
+                    .map(|link| link.target)
+                    .collect(),
+                Err(error) => {
+                    error!("error reading links in {}: {}", note.name, error);
+                    vec![]
+                }
+            };
+            self.links.insert(
+                note.relative_path.clone(),
+                (note.modified, note.size, targets),
+            );
+        }
+    }
+
+    // The names of the other notes with a link to `note`, out of `notes` sorted by name.
+    pub fn get_backlinks(&self, note: &NoteEntry, notes: &[Rc<NoteEntry>]) -> Vec<String> {
+        notes
+            .iter()
+            .filter(|other| other.relative_path != note.relative_path)
+            .filter(|other| {
+                self.links
+                    .get(&other.relative_path)
+                    .is_some_and(|(_, _, targets)| {
+                        targets
+                            .iter()
+                            .any(|target| resolves_to(target, note, notes))
+                    })
+            })
+            .map(|other| other.name.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(name: &str) -> NoteEntry {
+        NoteEntry::new(
+            PathBuf::from("/notes").join(name),
+            name.to_string(),
+            SystemTime::UNIX_EPOCH,
+            false,
+            0,
+        )
+    }
+
+    fn rewrite(content: &str, old: &str, new: &str, others: &[&str]) -> Option<(String, usize)> {
+        let mut notes: Vec<Rc<NoteEntry>> = [old]
+            .iter()
+            .chain(others)
+            .map(|name| Rc::new(note(name)))
+            .collect();
+        notes.sort_by(|a, b| a.name.cmp(&b.name));
+        rewrite_links(content, &note(old), &note(new), &notes)
+    }
+
+    #[test]
+    fn keeps_the_way_links_were_written() {
+        let content = "[[work/todo.md]] [[work/todo]] [[todo]]\n";
+        assert_eq!(
+            rewrite(content, "work/todo.md", "home/chores.md", &[]),
+            Some((
+                String::from("[[home/chores.md]] [[home/chores]] [[chores]]\n"),
+                3
+            ))
+        );
+    }
+
+    #[test]
+    fn keeps_headings_and_labels() {
+        let content = "see [[todo#today]] and [[todo|the list]]\n[[todo#today|what's next]]";
+        assert_eq!(
+            rewrite(content, "todo.md", "chores.md", &[]),
+            Some((
+                String::from(
+                    "see [[chores#today]] and [[chores|the list]]\n[[chores#today|what's next]]"
+                ),
+                3
+            ))
+        );
+    }
+
+    #[test]
+    fn leaves_links_to_other_notes_alone() {
+        assert_eq!(
+            rewrite("[[other]] [[todo]]", "todo.md", "chores.md", &["other.md"]),
+            Some((String::from("[[other]] [[chores]]"), 1))
+        );
+        assert_eq!(
+            rewrite("[[other]]", "todo.md", "chores.md", &["other.md"]),
+            None
+        );
+    }
+
+    #[test]
+    fn leaves_file_names_that_resolve_to_another_note_alone() {
+        // [[todo]] goes to a/todo.md, the first by name, so renaming b/todo.md doesn't touch it.
+        assert_eq!(
+            rewrite("[[todo]]", "b/todo.md", "b/done.md", &["a/todo.md"]),
+            None
+        );
+    }
+}
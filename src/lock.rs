@@ -0,0 +1,209 @@
+use crate::config::Config;
+use crate::note_entry::NoteEntry;
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use toml::{Table, Value};
+
+// Locks from other hosts can't be checked for a live process, so they're considered stale after
+// this long.
+const REMOTE_LOCK_STALE_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Who holds a lock on a note, as recorded in its lockfile.
+#[derive(Clone)]
+pub struct LockInfo {
+    pub host: String,
+    pub pid: u32,
+    pub timestamp: SystemTime,
+    stale: bool,
+}
+
+impl LockInfo {
+    fn current() -> Self {
+        LockInfo {
+            host: hostname(),
+            pid: std::process::id(),
+            timestamp: SystemTime::now(),
+            stale: false,
+        }
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    // A lock is stale when the process holding it is gone. That can only be checked on the same
+    // host, so locks from other hosts fall back to their age.
+    fn check_stale(&self) -> bool {
+        if self.host == hostname() {
+            return !process_is_alive(self.pid);
+        }
+
+        SystemTime::now()
+            .duration_since(self.timestamp)
+            .unwrap_or_default()
+            > REMOTE_LOCK_STALE_AFTER
+    }
+
+    pub fn describe(&self) -> String {
+        let date: chrono::DateTime<chrono::Local> = self.timestamp.into();
+        format!(
+            "pid {} on {} since {}",
+            self.pid,
+            self.host,
+            date.format("%b %d %I:%M")
+        )
+    }
+
+    fn to_toml(&self) -> String {
+        let mut table = Table::new();
+        table.insert(String::from("host"), Value::String(self.host.clone()));
+        table.insert(String::from("pid"), Value::Integer(self.pid as i64));
+        table.insert(
+            String::from("timestamp"),
+            Value::Integer(
+                self.timestamp
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs() as i64,
+            ),
+        );
+        table.to_string()
+    }
+
+    fn from_toml(contents: &str) -> Option<Self> {
+        let table = contents.parse::<Table>().ok()?;
+        let mut lock = LockInfo {
+            host: table.get("host")?.as_str()?.to_owned(),
+            pid: table.get("pid")?.as_integer()? as u32,
+            timestamp: UNIX_EPOCH
+                + Duration::from_secs(table.get("timestamp")?.as_integer()?.max(0) as u64),
+            stale: false,
+        };
+        lock.stale = lock.check_stale();
+        Some(lock)
+    }
+}
+
+// Advisory locks, one lockfile per note, so several instances sharing a notebook (e.g. over a
+// network mount) can tell when a note is already open in someone's editor.
+pub struct NoteLocks {
+    directory: PathBuf,
+}
+
+impl NoteLocks {
+    pub fn new(config: &Config) -> Self {
+        NoteLocks {
+            directory: PathBuf::from(config.get_lock_directory()),
+        }
+    }
+
+    fn get_lock_path(&self, note: &NoteEntry) -> PathBuf {
+        let mut lock_path = self.directory.clone();
+        lock_path.push(get_lock_file_name(note));
+        lock_path
+    }
+
+    // Attach lock info to every locked note. The lock directory is read once rather than
+    // checking for a lockfile per note, since it may well be on a slow network mount.
+    pub fn annotate(&self, notes: Vec<Rc<NoteEntry>>) -> Vec<Rc<NoteEntry>> {
+        let Ok(lock_files) = fs::read_dir(&self.directory) else {
+            return notes;
+        };
+        let locked: HashSet<String> = lock_files
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .collect();
+        if locked.is_empty() {
+            return notes;
+        }
+
+        notes
+            .into_iter()
+            .map(|note| {
+                if !locked.contains(&get_lock_file_name(&note)) {
+                    return note;
+                }
+                let mut locked_note = (*note).clone();
+                locked_note.lock = self.get_lock(&note);
+                Rc::new(locked_note)
+            })
+            .collect()
+    }
+
+    pub fn get_lock(&self, note: &NoteEntry) -> Option<LockInfo> {
+        let contents = fs::read_to_string(self.get_lock_path(note)).ok()?;
+        let lock = LockInfo::from_toml(&contents);
+        if lock.is_none() {
+            warn!("ignoring unreadable lockfile for {}", note.name);
+        }
+        lock
+    }
+
+    // Take the lock on a note. Returns None if someone else got there first.
+    pub fn acquire(&self, note: &NoteEntry) -> Result<Option<LockGuard>> {
+        fs::create_dir_all(&self.directory).context("error creating lock directory")?;
+
+        let lock_path = self.get_lock_path(note);
+        let mut lock_file = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+        {
+            Ok(lock_file) => lock_file,
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => return Ok(None),
+            Err(error) => return Err(error).context("error creating lockfile"),
+        };
+        lock_file
+            .write_all(LockInfo::current().to_toml().as_bytes())
+            .context("error writing lockfile")?;
+
+        debug!("locked {}", note.name);
+        Ok(Some(LockGuard { path: lock_path }))
+    }
+
+    pub fn remove(&self, note: &NoteEntry) -> Result<()> {
+        fs::remove_file(self.get_lock_path(note)).context("error removing lockfile")
+    }
+}
+
+// Releases the lock when dropped.
+pub struct LockGuard {
+    path: PathBuf,
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_file(&self.path) {
+            warn!("could not remove lockfile {:?}: {}", self.path, error);
+        }
+    }
+}
+
+fn get_lock_file_name(note: &NoteEntry) -> String {
+    format!("{}.lock", note.name.replace('/', "%2F"))
+}
+
+fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    // SAFETY: the buffer is valid for writes of its full length.
+    let result =
+        unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) };
+    if result != 0 {
+        return String::from("unknown");
+    }
+
+    let end = buffer.iter().position(|b| *b == 0).unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..end]).to_string()
+}
+
+fn process_is_alive(pid: u32) -> bool {
+    // SAFETY: signal 0 only checks whether the process exists, nothing is delivered.
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
@@ -1,46 +1,72 @@
 mod actions;
+mod cli;
+mod commands;
 mod config;
+mod events;
+mod filter;
+mod journal;
+mod keys;
+mod links;
+mod lock;
+mod markdown;
 mod navigation_state;
 mod note_entry;
+mod pins;
 mod prompt;
 mod providers;
 mod render;
+mod search;
+mod session;
+mod status_bar;
+mod tags;
+mod templates;
+mod trash_entry;
+mod undo;
 
-use crate::actions::{create_note, delete_note, rename_note};
+use crate::actions::{
+    archive_notes, check_lock, clone_note, create_folder, create_named_note, create_note,
+    delete_notes, edit_note, export_notes, find_note, move_note, move_notes, purge_expired_trash,
+    purge_note, rename_note, restore_note, retag_notes, update_links,
+};
+use crate::cli::{run_command, Commands};
+use crate::commands::Command;
 use crate::config::Config;
+use crate::events::{Event, Events};
+use crate::filter::find_matches;
+use crate::journal::{
+    get_adjacent_entry, get_calendar_strip, get_journal_date, open_journal, JournalEntries,
+};
+use crate::keys::{Action, Keymap};
+use crate::links::{find_links, resolve, LinkIndex};
+use crate::lock::NoteLocks;
 use crate::navigation_state::{NavigationState, SortDir, SortField};
-use crate::prompt::clear;
+use crate::note_entry::NoteEntry;
+use crate::pins::Pins;
+use crate::prompt::{clear, prompt, prompt_command};
 use crate::providers::file_system_provider::FileSystemNotesProvider;
-use crate::providers::provider::NotesProvider;
-use crate::render::{table, Column, Columnar, Field};
+use crate::providers::provider::{sort_notes, NotesProvider};
+use crate::providers::sqlite_provider::SqliteNotesProvider;
+use crate::render::{calendar, overlay, table, CalendarStrip, Column, Columnar, Field, Preview};
+use crate::search::{search_notes, SearchResult};
+use crate::session::{load_session, save_session};
+use crate::status_bar::StatusBar;
+use crate::tags::{count_tags, TagCount, TagExpression};
+use crate::templates::{get_templates, TemplateEntry};
+use crate::undo::{Operation, UndoHistory};
 
 use anyhow::{bail, Context, Result};
-use clap::Parser;
-use log::{error, warn, LevelFilter};
-use std::io::{stdin, stdout, Stdout, Write};
-use std::process::{Command, Stdio};
+use chrono::Local;
+use clap::{CommandFactory, Parser};
+use log::{error, LevelFilter};
+use std::io::{stdout, Stdout, Write};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::str::FromStr;
-use std::time::{Duration, Instant};
+use std::time::Instant;
 use termion::event::Key;
-use termion::input::TermRead;
 use termion::raw::IntoRawMode;
 use termion::raw::RawTerminal;
 
-enum Action {
-    Quit,
-    Noop,
-    OpenEditor,
-    Rename,
-    Delete,
-    New,
-    NavDown,
-    NavUp,
-    NavTop,
-    NavBottom,
-    Sort,
-}
-
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
@@ -52,6 +78,9 @@ struct Args {
         help = "Generate a default configuration toml to be used in ~/.noteconfig"
     )]
     example_config: bool,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
 }
 
 fn main() -> Result<()> {
@@ -85,23 +114,57 @@ fn main() -> Result<()> {
         .context("parsing config file into toml")?;
     let config = Config::new(config_toml);
 
-    // Eventually, we'll add other providers. SQLite hopefully.
-    let notes_provider = FileSystemNotesProvider::new(&config);
+    match config.get_provider() {
+        "filesystem" => start(
+            &FileSystemNotesProvider::new(&config),
+            &config,
+            args.command,
+        ),
+        "sqlite" => start(&SqliteNotesProvider::new(&config)?, &config, args.command),
+        provider => bail!(
+            "Unknown provider '{}' in config. Expected 'filesystem' or 'sqlite'.",
+            provider
+        ),
+    }
+}
 
+fn start<T: NotesProvider>(
+    notes_provider: &T,
+    config: &Config,
+    command: Option<Commands>,
+) -> Result<()> {
     // Check the notes dir and default file exist
     notes_provider.validate_default_note_exists()?;
 
-    // Create stdout and stdin for the main application loop
+    // Subcommands run without the TUI.
+    if let Some(command) = command {
+        return run_command(command, notes_provider, config);
+    }
+
+    // Not being able to tidy up the trash shouldn't stop anyone getting to their notes.
+    if let Err(error) = purge_expired_trash(notes_provider, config) {
+        error!("error purging old notes from the trash: {:#}", error);
+    }
+    let keymap = Keymap::new(config)?;
+
+    // Create stdout and the input events for the main application loop
     let mut stdout = stdout()
         .into_raw_mode()
         .context("Could not open stdout. Something went very wrong")?;
-    let stdin = stdin();
+    let mut events = Events::new()?;
 
-    // TODO let's eventually save navigation state across sessions.
     let state = NavigationState::new(0);
 
     // Main application loop
-    run(&notes_provider, state, &mut stdout, &stdin, &config).inspect_err(|e| {
+    run(
+        notes_provider,
+        state,
+        &mut stdout,
+        &mut events,
+        config,
+        &keymap,
+    )
+    .inspect_err(|e| {
         error!("{}", e.to_string());
     })?;
 
@@ -113,109 +176,630 @@ fn run<T: NotesProvider>(
     notes_provider: &T,
     mut state: NavigationState,
     stdout: &mut RawTerminal<Stdout>,
-    stdin: &std::io::Stdin,
+    events: &mut Events,
     config: &Config,
+    keymap: &Keymap,
 ) -> Result<()> {
-    let columns = vec![
+    let mut columns = vec![
         Column {
             field: Field::Name,
             name: "Name".to_string(),
-            sort_field: SortField::Name,
+            sort_field: Some(SortField::Name),
         },
         Column {
             field: Field::Size,
             name: "Size".to_string(),
-            sort_field: SortField::Size,
+            sort_field: Some(SortField::Size),
         },
         Column {
             field: Field::Modified,
             name: "Modified".to_string(),
-            sort_field: SortField::Modified,
+            sort_field: Some(SortField::Modified),
+        },
+        Column {
+            field: Field::Tags,
+            name: "Tags".to_string(),
+            sort_field: None,
         },
     ];
-    let footer = "New file [n]; Rename file [r]; Delete file [dd]; Sort[s]; Quit [q]";
+    let footer = keymap.get_footer(&[
+        (Action::Help, "Help"),
+        (Action::CommandLine, "Command"),
+        (Action::New, "New file"),
+        (Action::Rename, "Rename file"),
+        (Action::Clone, "Clone file"),
+        (Action::Move, "Move file"),
+        (Action::Delete, "Delete file"),
+        (Action::NewFolder, "New folder"),
+        (Action::ParentFolder, "Up a folder"),
+        (Action::Sort, "Sort"),
+        (Action::Filter, "Filter"),
+        (Action::Search, "Search"),
+        (Action::Trash, "Trash"),
+        (Action::TogglePreview, "Preview"),
+        (Action::Quit, "Quit"),
+    ]);
 
-    let mut note_list = notes_provider.get_notes(state.get_sort_field(), state.get_sort_dir());
-    state.set_list_size(note_list.len() as u16);
+    let mut status_bar = StatusBar::new(footer.clone());
+    let locks = NoteLocks::new(config);
+    let pins = Pins::new(config);
 
-    let mut rows: Vec<Rc<dyn Columnar>> = note_list
-        .iter()
-        .map(|file| file.clone() as Rc<dyn Columnar>)
-        .collect();
-    write!(stdout, "{}", table::draw(&rows, &columns, footer, &state))?;
-    stdout.flush()?;
+    // Pick up where the last session left off.
+    let saved_selection = load_session(&mut state, notes_provider, config);
+    let mut note_list = get_filtered_notes(notes_provider, &state, &locks, &pins);
+    state.set_list_size(note_list.len() as u16);
+    if let Some(saved_selection) = saved_selection {
+        state.set_selected_index(saved_selection.find(&note_list));
+    }
+    let mut rows = to_rows(&note_list);
 
     let mut key_buffer: Vec<Key> = vec![];
     let mut last_keypress_time = Instant::now();
-    for event_opt in stdin.keys() {
-        let event = match event_opt {
-            Ok(event) => event,
-            Err(error) => {
-                warn!(
-                    "error occured when processing keystroke. Retrying. {}",
-                    error
-                );
-                continue;
-            }
+    let mut command_history = vec![];
+    let mut history = UndoHistory::load(config);
+    let mut link_index = LinkIndex::default();
+    let mut journal_entries = JournalEntries::default();
+    loop {
+        state.fit_to_terminal();
+        status_bar.set_footer(get_footer(&footer, &state, keymap));
+        columns[0].name = get_breadcrumb(&state);
+        let preview = get_preview(
+            notes_provider,
+            &note_list,
+            &mut state,
+            config,
+            &mut link_index,
+        );
+        let calendar = get_journal_calendar(
+            notes_provider,
+            &note_list,
+            &state,
+            config,
+            &mut journal_entries,
+        )
+        .map_or(String::new(), |strip| {
+            calendar::draw(&strip, &rows, &columns, preview.as_ref())
+        });
+        write!(
+            stdout,
+            "{table}{calendar}",
+            table = table::draw(&rows, &columns, &status_bar, &state, preview.as_ref())
+        )?;
+        stdout.flush()?;
+
+        // Wake up when a flashed message expires so it gets cleared off the screen, or when the
+        // terminal is resized.
+        let Some(Event::Key(event)) = events.next(status_bar.get_timeout())? else {
+            continue;
         };
 
-        match handle_key(event, &mut key_buffer, &mut last_keypress_time) {
-            Action::Quit => break,
-            Action::NavDown => {
-                state.increment_selected_index(1);
+        let (action, count) = keymap.handle_key(event, &mut key_buffer, &mut last_keypress_time);
+        let command = match action {
+            Action::CommandLine => read_command_line(
+                notes_provider,
+                stdout,
+                events,
+                &mut command_history,
+                &mut status_bar,
+            )?,
+            action => Command::from(action),
+        };
+
+        // Anything that touches the notes could add or remove a journal entry, and the journal
+        // commands themselves go by the latest ones.
+        if command.changes_notes() {
+            journal_entries.clear();
+        }
+
+        match command {
+            Command::Quit => break,
+            Command::Navigate(action) => {
+                state.navigate(&action, count);
             }
-            Action::NavUp => {
-                state.decrement_selected_index(1);
+            Command::Rename(name) => {
+                let Some(selected_note) =
+                    get_selected_note(&note_list, &state, &mut status_bar, "renamed")
+                else {
+                    continue;
+                };
+                if !check_lock(selected_note, &locks, stdout, events, "rename")? {
+                    continue;
+                }
+                let Some(renamed) = rename_note(
+                    selected_note,
+                    notes_provider,
+                    config,
+                    stdout,
+                    events,
+                    &mut status_bar,
+                    name,
+                )?
+                else {
+                    continue;
+                };
+                let path = renamed.get_relative_path().to_path_buf();
+                let operations = update_links(
+                    renamed,
+                    notes_provider,
+                    &locks,
+                    stdout,
+                    events,
+                    &mut status_bar,
+                )?;
+                move_marks(&mut state, &operations);
+                history.record(operations);
+
+                // The new name can sort somewhere else, so the selection follows the note.
+                let index = get_filtered_notes(notes_provider, &state, &locks, &pins)
+                    .iter()
+                    .position(|note| note.relative_path == path);
+                state.set_selected_index(index.unwrap_or(0));
+            }
+            Command::Clone(name) => {
+                let Some(selected_note) =
+                    get_selected_note(&note_list, &state, &mut status_bar, "cloned")
+                else {
+                    continue;
+                };
+                history.record(clone_note(
+                    selected_note,
+                    notes_provider,
+                    config,
+                    stdout,
+                    events,
+                    &mut status_bar,
+                    name,
+                )?);
             }
-            Action::NavTop => {
-                state.set_selected_index(note_list.len() - 1);
+            Command::Move(folder) if state.has_marks() => {
+                let notes = get_target_notes(
+                    notes_provider,
+                    &note_list,
+                    &mut state,
+                    &mut status_bar,
+                    "moved",
+                );
+                let notes = get_unlocked(notes, &locks, stdout, events, "move")?;
+                let moved = move_notes(
+                    &notes,
+                    notes_provider,
+                    config,
+                    stdout,
+                    events,
+                    &mut status_bar,
+                    folder,
+                )?;
+                unmark_done(&mut state, &moved);
+                history.record(moved);
             }
-            Action::NavBottom => {
+            Command::Move(folder) => {
+                let Some(selected_note) =
+                    get_selected_note(&note_list, &state, &mut status_bar, "moved")
+                else {
+                    continue;
+                };
+                if !check_lock(selected_note, &locks, stdout, events, "move")? {
+                    continue;
+                }
+                let moved = move_note(
+                    selected_note,
+                    notes_provider,
+                    config,
+                    stdout,
+                    events,
+                    &mut status_bar,
+                    folder,
+                )?;
+                move_marks(&mut state, moved.as_slice());
+                history.record(moved);
+            }
+            Command::New(name) => {
+                let templates = get_templates(config);
+                let template = match templates.is_empty() {
+                    true => None,
+                    false => {
+                        match run_template_picker(
+                            templates,
+                            stdout,
+                            events,
+                            &mut status_bar,
+                            keymap,
+                        )? {
+                            Some(template) => template,
+                            None => continue,
+                        }
+                    }
+                };
+                let created = create_note(
+                    notes_provider,
+                    config,
+                    state.get_folder(),
+                    stdout,
+                    events,
+                    name,
+                    template.as_deref(),
+                );
+                let (note, operation, cursor) = match created {
+                    Ok(Some(created)) => created,
+                    Ok(None) => continue,
+                    Err(error) => {
+                        status_bar.flash(format!("{:#}", error));
+                        continue;
+                    }
+                };
+                history.record([operation]);
+                status_bar.flash(format!("Created {}", note.name));
+                // Templates with a {{cursor}} are there to be filled in straight away.
+                if let Some(line) = cursor {
+                    let note = Rc::new(note);
+                    go_to_note(
+                        notes_provider,
+                        &mut state,
+                        &locks,
+                        &pins,
+                        &note.relative_path,
+                    );
+                    if check_lock(&note, &locks, stdout, events, "open")? {
+                        if let Err(error) = edit_note(&note, notes_provider, Some(line), &locks) {
+                            status_bar.flash(format!("{:#}", error));
+                        }
+                    }
+                }
+            }
+            Command::NewFolder(name) => {
+                create_folder(
+                    notes_provider,
+                    config,
+                    state.get_folder(),
+                    stdout,
+                    events,
+                    &mut status_bar,
+                    name,
+                )?;
+            }
+            Command::ChangeFolder(folder) => {
+                let folder = PathBuf::from(folder.trim_matches('/'));
+                let exists = folder.as_os_str().is_empty()
+                    || notes_provider
+                        .get_folders()?
+                        .iter()
+                        .any(|existing| existing.relative_path == folder);
+                if !exists {
+                    status_bar.flash(format!("No folder named {}", folder.to_string_lossy()));
+                    continue;
+                }
+                state.set_folder(folder);
+                state.clear_filter();
                 state.set_selected_index(0);
             }
-            Action::Rename => {
-                let selected_note = &note_list[state.get_selected_index()];
-                rename_note(selected_note, notes_provider, config, stdout, stdin)?;
+            Command::ParentFolder => {
+                let folder = state.get_folder().to_path_buf();
+                let Some(parent) = folder.parent() else {
+                    continue;
+                };
+                state.set_folder(parent.to_path_buf());
+                state.clear_filter();
 
-                // TODO update this to find the index of the new note, taking into account the
-                // current sort state
-                state.set_selected_index(0);
+                // Land on the folder we just came out of.
+                note_list = get_filtered_notes(notes_provider, &state, &locks, &pins);
+                state.set_list_size(note_list.len() as u16);
+                let index = note_list
+                    .iter()
+                    .position(|note| note.is_folder && note.relative_path == folder);
+                state.set_selected_index(index.unwrap_or(0));
             }
-            Action::New => {
-                create_note(notes_provider, config, stdout, stdin)?;
+            Command::Delete => {
+                // A count deletes that many notes from the selection down, skipping folders.
+                let notes_to_del: Vec<Rc<NoteEntry>> = match count {
+                    Some(count) if !state.has_marks() => note_list
+                        .iter()
+                        .skip(state.get_selected_index())
+                        .take(count)
+                        .filter(|note| !note.is_folder)
+                        .cloned()
+                        .collect(),
+                    _ => get_target_notes(
+                        notes_provider,
+                        &note_list,
+                        &mut state,
+                        &mut status_bar,
+                        "deleted",
+                    ),
+                };
+                let notes_to_del = get_unlocked(notes_to_del, &locks, stdout, events, "delete")?;
+                // The selection is kept inside the shorter list when it's refreshed below.
+                let deleted = delete_notes(
+                    &notes_to_del,
+                    notes_provider,
+                    stdout,
+                    events,
+                    &mut status_bar,
+                )
+                .context("error deleting note")?;
+                unmark_done(&mut state, &deleted);
+                history.record(deleted);
             }
-            Action::Delete => {
-                let note_to_del = &note_list[state.get_selected_index()];
-                match delete_note(note_to_del, notes_provider, config, stdout, stdin) {
-                    Ok(true) => {
-                        // Note was deleted
-                        if state.get_selected_index() > note_list.len() - 2 {
-                            state.set_selected_index(state.get_selected_index().saturating_sub(1));
+            Command::Archive => {
+                let notes = get_target_notes(
+                    notes_provider,
+                    &note_list,
+                    &mut state,
+                    &mut status_bar,
+                    "archived",
+                );
+                let notes = get_unlocked(notes, &locks, stdout, events, "archive")?;
+                let archived = archive_notes(
+                    &notes,
+                    notes_provider,
+                    config,
+                    stdout,
+                    events,
+                    &mut status_bar,
+                )?;
+                unmark_done(&mut state, &archived);
+                history.record(archived);
+            }
+            Command::Export(directory) => {
+                // The notes stay where they are, so they stay marked too.
+                let notes = get_target_notes(
+                    notes_provider,
+                    &note_list,
+                    &mut state,
+                    &mut status_bar,
+                    "exported",
+                );
+                export_notes(
+                    &notes,
+                    notes_provider,
+                    stdout,
+                    events,
+                    &mut status_bar,
+                    directory,
+                )?;
+            }
+            Command::ToggleMark => {
+                let Some(selected_note) =
+                    get_selected_note(&note_list, &state, &mut status_bar, "marked")
+                else {
+                    continue;
+                };
+                state.toggle_mark(selected_note.relative_path.clone());
+                state.move_down(1);
+            }
+            Command::TogglePin => {
+                let Some(selected_note) =
+                    get_selected_note(&note_list, &state, &mut status_bar, "pinned")
+                else {
+                    continue;
+                };
+                match pins.toggle(selected_note) {
+                    Ok(true) => status_bar.flash(format!("Pinned {}", selected_note.name)),
+                    Ok(false) => status_bar.flash(format!("Unpinned {}", selected_note.name)),
+                    Err(error) => status_bar.flash(format!("{:#}", error)),
+                }
+                // The note moves to or from the top, so the selection follows it.
+                let path = selected_note.relative_path.clone();
+                let index = get_filtered_notes(notes_provider, &state, &locks, &pins)
+                    .iter()
+                    .position(|note| note.relative_path == path);
+                state.set_selected_index(index.unwrap_or(0));
+            }
+            Command::VisualMark => {
+                if state.is_visual() {
+                    finish_visual(&mut state, &note_list);
+                } else {
+                    state.start_visual();
+                }
+            }
+            Command::ClearMarks => {
+                state.clear_marks();
+            }
+            Command::Undo => {
+                match history.undo(notes_provider, config) {
+                    Ok(Some(message)) => status_bar.flash(message),
+                    Ok(None) => status_bar.flash(String::from("Nothing to undo.")),
+                    Err(error) => status_bar.flash(format!("{:#}", error)),
+                }
+                for (from, to) in history.take_moved() {
+                    state.move_mark(Path::new(&from), PathBuf::from(to));
+                }
+            }
+            Command::Redo => {
+                match history.redo(notes_provider, config) {
+                    Ok(Some(message)) => status_bar.flash(message),
+                    Ok(None) => status_bar.flash(String::from("Nothing to redo.")),
+                    Err(error) => status_bar.flash(format!("{:#}", error)),
+                }
+                for (from, to) in history.take_moved() {
+                    state.move_mark(Path::new(&from), PathBuf::from(to));
+                }
+            }
+            Command::TagFilter(tag_filter) => {
+                let tag_filter = match tag_filter {
+                    Some(tag_filter) => Some(tag_filter),
+                    None => prompt(
+                        stdout,
+                        events,
+                        String::from("Show notes tagged (e.g. work AND NOT done): "),
+                    )?,
+                };
+                match tag_filter.as_deref().map(str::trim) {
+                    None => {}
+                    Some("") => state.clear_tag_filter(),
+                    Some(tag_filter) => match TagExpression::parse(tag_filter) {
+                        Ok(_) => {
+                            state.set_tag_filter(tag_filter.to_string());
+                            state.set_selected_index(0);
                         }
+                        Err(error) => status_bar.flash(error.to_string()),
+                    },
+                }
+            }
+            Command::FollowLink(number) => {
+                let Some(selected_note) =
+                    get_selected_note(&note_list, &state, &mut status_bar, "linked from")
+                else {
+                    continue;
+                };
+                let content = match notes_provider.read_note(selected_note) {
+                    Ok(content) => content,
+                    Err(error) => {
+                        status_bar.flash(format!(
+                            "Could not read {}: {:#}",
+                            selected_note.name, error
+                        ));
+                        continue;
                     }
-                    Ok(false) => {
-                        // Note was not deleted
+                };
+                let links = find_links(&content);
+                let link = match number.or(count) {
+                    Some(number) => links.get(number - 1),
+                    // The first link in view, if the preview has been scrolled past some.
+                    None => {
+                        let scroll = match state.is_preview_open() {
+                            true => state.get_preview_scroll(),
+                            false => 0,
+                        };
+                        links
+                            .iter()
+                            .find(|link| link.line >= scroll)
+                            .or(links.first())
                     }
-                    Err(error) => Err(error).context("error deleting note")?,
                 };
+                let Some(link) = link else {
+                    status_bar.flash(match links.len() {
+                        0 => format!("{} has no links.", selected_note.name),
+                        1 => format!("{} only has 1 link.", selected_note.name),
+                        count => format!("{} only has {} links.", selected_note.name, count),
+                    });
+                    continue;
+                };
+
+                // Links to notes that don't exist yet create them.
+                let notes = notes_provider.get_notes(&SortField::Name, &SortDir::Asc);
+                let target = match resolve(&link.target, &notes) {
+                    Some(note) => note.relative_path.clone(),
+                    None => {
+                        let created = create_named_note(notes_provider, config, &link.target, "");
+                        let (note, operation) = match created {
+                            Ok(created) => created,
+                            Err(error) => {
+                                status_bar.flash(format!("{:#}", error));
+                                continue;
+                            }
+                        };
+                        history.record([operation]);
+                        status_bar.flash(format!("Created {}", note.name));
+                        note.relative_path
+                    }
+                };
+                go_to_note(notes_provider, &mut state, &locks, &pins, &target);
             }
-            Action::OpenEditor => {
-                // TODO this doesn't work if we eventually convert to not using the FS provider
-                let file_path = note_list[state.get_selected_index()]
-                    .path
-                    .to_str()
-                    .context("could not convert file path to string")?;
-
-                let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
-
-                Command::new(editor)
-                    .args([file_path])
-                    .stdin(Stdio::inherit())
-                    .stdout(Stdio::inherit())
-                    .output()
-                    .context("Failed to launch editor.")?;
-            }
-            Action::Sort => {
+            Command::Journal(date) => {
+                let date = date.unwrap_or_else(|| Local::now().date_naive());
+                let (note, operation, cursor) = match open_journal(notes_provider, config, date) {
+                    Ok(journal) => journal,
+                    Err(error) => {
+                        status_bar.flash(format!("{:#}", error));
+                        continue;
+                    }
+                };
+                history.record(operation);
+                go_to_note(
+                    notes_provider,
+                    &mut state,
+                    &locks,
+                    &pins,
+                    &note.relative_path,
+                );
+                if check_lock(&note, &locks, stdout, events, "open")? {
+                    if let Err(error) = edit_note(&note, notes_provider, cursor, &locks) {
+                        status_bar.flash(format!("{:#}", error));
+                    }
+                }
+            }
+            Command::JournalPrevious | Command::JournalNext => {
+                let forward = matches!(command, Command::JournalNext);
+                let entries = journal_entries.get(notes_provider, config);
+                // From anything but an entry, previous starts with today's.
+                let today = Local::now().date_naive();
+                let from = note_list
+                    .get(state.get_selected_index())
+                    .and_then(|note| get_journal_date(config, note))
+                    .unwrap_or(match forward {
+                        true => today,
+                        false => today.succ_opt().unwrap_or(today),
+                    });
+                match get_adjacent_entry(entries, from, forward, count.unwrap_or(1)) {
+                    Some(note) => go_to_note(
+                        notes_provider,
+                        &mut state,
+                        &locks,
+                        &pins,
+                        &note.relative_path,
+                    ),
+                    None if forward => status_bar.flash(String::from("No later journal entries.")),
+                    None => status_bar.flash(String::from("No earlier journal entries.")),
+                }
+            }
+            Command::ClearTagFilter => {
+                state.clear_tag_filter();
+            }
+            Command::TagBrowser => {
+                if let Some(tag) =
+                    run_tag_browser(notes_provider, stdout, events, &mut status_bar, keymap)?
+                {
+                    state.set_tag_filter(tag);
+                    state.set_selected_index(0);
+                }
+            }
+            Command::Retag(changes) => {
+                let notes = get_target_notes(
+                    notes_provider,
+                    &note_list,
+                    &mut state,
+                    &mut status_bar,
+                    "tagged",
+                );
+                let notes = get_unlocked(notes, &locks, stdout, events, "retag")?;
+                history.record(retag_notes(
+                    &notes,
+                    notes_provider,
+                    stdout,
+                    events,
+                    &mut status_bar,
+                    changes,
+                )?);
+            }
+            Command::Open(Some(name)) => match find_note(notes_provider, config, &name) {
+                Ok(note) => {
+                    if check_lock(&note, &locks, stdout, events, "open")? {
+                        if let Err(error) = edit_note(&note, notes_provider, None, &locks) {
+                            status_bar.flash(format!("{:#}", error));
+                        }
+                    }
+                }
+                Err(error) => status_bar.flash(error.to_string()),
+            },
+            Command::Open(None) => {
+                let Some(selected_note) = note_list.get(state.get_selected_index()) else {
+                    continue;
+                };
+                if selected_note.is_folder {
+                    state.set_folder(selected_note.relative_path.clone());
+                    state.clear_filter();
+                    state.set_selected_index(0);
+                } else if check_lock(selected_note, &locks, stdout, events, "open")? {
+                    if let Err(error) = edit_note(selected_note, notes_provider, None, &locks) {
+                        status_bar.flash(format!("{:#}", error));
+                    }
+                }
+            }
+            Command::Sort(Some(sort_field), sort_dir) => match sort_dir {
+                Some(sort_dir) => state.set_sort(sort_field, sort_dir),
+                None => state.sort(sort_field),
+            },
+            Command::Sort(None, _) => {
                 // Toggle between sort modes
 
                 // TODO This is pretty janky right now. I think the columns could be passed a navigation
@@ -224,29 +808,50 @@ fn run<T: NotesProvider>(
                     Column {
                         field: Field::Name,
                         name: "[n] Name".to_string(),
-                        sort_field: SortField::Name,
+                        sort_field: Some(SortField::Name),
                     },
                     Column {
                         field: Field::Size,
                         name: "[s] Size".to_string(),
-                        sort_field: SortField::Size,
+                        sort_field: Some(SortField::Size),
                     },
                     Column {
                         field: Field::Modified,
                         name: "[m] Modified".to_string(),
-                        sort_field: SortField::Modified,
+                        sort_field: Some(SortField::Modified),
+                    },
+                    Column {
+                        field: Field::Tags,
+                        name: "Tags".to_string(),
+                        sort_field: None,
                     },
                 ];
 
-                write!(
-                    stdout,
-                    "{}",
-                    table::draw(&rows, &sorted_columns, footer, &state)
-                )?;
-                stdout.flush()?;
+                loop {
+                    state.fit_to_terminal();
+                    let preview = get_preview(
+                        notes_provider,
+                        &note_list,
+                        &mut state,
+                        config,
+                        &mut link_index,
+                    );
+                    write!(
+                        stdout,
+                        "{}",
+                        table::draw(
+                            &rows,
+                            &sorted_columns,
+                            &status_bar,
+                            &state,
+                            preview.as_ref()
+                        )
+                    )?;
+                    stdout.flush()?;
 
-                for k_event in stdin.keys() {
-                    let key = k_event.context("could not read input")?;
+                    let Some(Event::Key(key)) = events.next(None)? else {
+                        continue;
+                    };
                     match key {
                         Key::Char('s') => {
                             state.sort(SortField::Size);
@@ -264,59 +869,858 @@ fn run<T: NotesProvider>(
                     };
                 }
             }
-            Action::Noop => {}
+            Command::Filter(Some(filter)) => {
+                state.set_filter(filter);
+            }
+            Command::Filter(None) => {
+                // Narrow the list as the query is typed. Enter keeps the filter, Esc clears it.
+                let mut filter = state.get_filter().to_string();
+                loop {
+                    state.set_filter(filter.clone());
+                    note_list = get_filtered_notes(notes_provider, &state, &locks, &pins);
+                    rows = to_rows(&note_list);
+                    state.set_list_size(note_list.len() as u16);
+                    state.fit_to_terminal();
+                    status_bar.set_footer(format!("/{}", filter));
+                    let preview = get_preview(
+                        notes_provider,
+                        &note_list,
+                        &mut state,
+                        config,
+                        &mut link_index,
+                    );
+                    write!(
+                        stdout,
+                        "{}",
+                        table::draw(&rows, &columns, &status_bar, &state, preview.as_ref())
+                    )?;
+                    stdout.flush()?;
+
+                    let Some(Event::Key(key)) = events.next(None)? else {
+                        continue;
+                    };
+                    match key {
+                        Key::Esc => {
+                            state.clear_filter();
+                            break;
+                        }
+                        Key::Char('\n') => break,
+                        Key::Backspace => {
+                            filter.pop();
+                        }
+                        Key::Char(c) => filter.push(c),
+                        _ => continue,
+                    };
+                }
+            }
+            Command::ClearFilter if state.has_marks() => {
+                state.clear_marks();
+            }
+            Command::ClearFilter if state.get_filter().is_empty() => {
+                state.clear_tag_filter();
+            }
+            Command::ClearFilter => {
+                state.clear_filter();
+            }
+            Command::TogglePreview => {
+                state.toggle_preview();
+            }
+            Command::PreviewDown => {
+                state.set_preview_scroll(state.get_preview_scroll().saturating_add(1));
+            }
+            Command::PreviewUp => {
+                state.set_preview_scroll(state.get_preview_scroll().saturating_sub(1));
+            }
+            Command::Trash => {
+                run_trash(
+                    notes_provider,
+                    &pins,
+                    &mut history,
+                    stdout,
+                    events,
+                    &mut status_bar,
+                    keymap,
+                )?;
+            }
+            Command::Help => {
+                run_help(stdout, events, keymap, || {
+                    table::draw(&rows, &columns, &status_bar, &state, preview.as_ref())
+                })?;
+            }
+            Command::Search(query) => {
+                let query = match query {
+                    Some(query) => Some(query),
+                    None => prompt(stdout, events, String::from("Search note contents: "))?,
+                };
+                if let Some(query) = query.filter(|query| !query.is_empty()) {
+                    run_search(
+                        notes_provider,
+                        &query,
+                        &locks,
+                        stdout,
+                        events,
+                        &mut status_bar,
+                        keymap,
+                    )?;
+                }
+            }
+            Command::Noop => {}
         }
 
-        note_list = notes_provider.get_notes(state.get_sort_field(), state.get_sort_dir());
-        rows = note_list
+        note_list = get_filtered_notes(notes_provider, &state, &locks, &pins);
+        rows = to_rows(&note_list);
+        state.set_list_size(note_list.len() as u16);
+    }
+
+    if let Err(error) = save_session(&state, &note_list, config) {
+        error!("could not save the session: {:#}", error);
+    }
+    Ok(())
+}
+
+// Show the lines matching `query` across every note until the user backs out with q or Esc.
+// Enter opens the note under the cursor at the matching line.
+fn run_search<T: NotesProvider>(
+    notes_provider: &T,
+    query: &str,
+    locks: &NoteLocks,
+    stdout: &mut RawTerminal<Stdout>,
+    events: &mut Events,
+    status_bar: &mut StatusBar,
+    keymap: &Keymap,
+) -> Result<()> {
+    let columns = vec![
+        Column {
+            field: Field::Name,
+            name: "Name".to_string(),
+            sort_field: None,
+        },
+        Column {
+            field: Field::Line,
+            name: "Line".to_string(),
+            sort_field: None,
+        },
+        Column {
+            field: Field::Snippet,
+            name: "Match".to_string(),
+            sort_field: None,
+        },
+    ];
+    status_bar.set_footer(format!(
+        "Results for '{}'. {}",
+        query,
+        keymap.get_footer(&[(Action::OpenEditor, "Open"), (Action::Quit, "Back")])
+    ));
+
+    let mut state = NavigationState::new(0);
+    let mut key_buffer: Vec<Key> = vec![];
+    let mut last_keypress_time = Instant::now();
+    let mut results = get_search_results(notes_provider, query, &state)?;
+    loop {
+        let rows: Vec<Rc<dyn Columnar>> = results
             .iter()
-            .map(|file| file.clone() as Rc<dyn Columnar>)
+            .map(|result| result.clone() as Rc<dyn Columnar>)
             .collect();
-        state.set_list_size(note_list.len() as u16);
+        state.set_list_size(rows.len() as u16);
+        state.fit_to_terminal();
         write!(
             stdout,
-            "{table}",
-            table = table::draw(&rows, &columns, footer, &state)
+            "{}",
+            table::draw(&rows, &columns, status_bar, &state, None)
         )?;
         stdout.flush()?;
+
+        let Some(Event::Key(key)) = events.next(status_bar.get_timeout())? else {
+            continue;
+        };
+        let (action, count) = keymap.handle_key(key, &mut key_buffer, &mut last_keypress_time);
+        if state.navigate(&action, count) {
+            continue;
+        }
+        match action {
+            Action::Quit | Action::ClearFilter => break,
+            Action::OpenEditor => {
+                if let Some(result) = results.get(state.get_selected_index()) {
+                    if !check_lock(&result.note, locks, stdout, events, "open")? {
+                        continue;
+                    }
+                    if let Err(error) = edit_note(
+                        &result.note,
+                        notes_provider,
+                        Some(result.line_number),
+                        locks,
+                    ) {
+                        status_bar.flash(format!("{:#}", error));
+                    }
+
+                    // The edit may have moved or removed matches, so search again.
+                    results = get_search_results(notes_provider, query, &state)?;
+                }
+            }
+            _ => {}
+        }
     }
 
     Ok(())
 }
 
-fn handle_key(
-    key_event: Key,
-    key_buffer: &mut Vec<Key>,
-    last_keypress_time: &mut Instant,
-) -> Action {
-    // Handle chords
-    key_buffer.push(key_event);
-    if *key_buffer == [Key::Char('g'), Key::Char('g')] {
-        key_buffer.clear();
-        *last_keypress_time = Instant::now();
-        return Action::NavBottom;
-    } else if *key_buffer == [Key::Char('d'), Key::Char('d')] {
-        key_buffer.clear();
-        *last_keypress_time = Instant::now();
-        return Action::Delete;
-    } else if key_buffer.len() == 2
-        || Instant::now().duration_since(*last_keypress_time) > Duration::from_millis(300)
-    {
-        key_buffer.clear();
-        key_buffer.push(key_event);
-        *last_keypress_time = Instant::now();
+// Read a line at the ':' command line and parse it. Mistakes are flashed in the status bar rather
+// than failing.
+fn read_command_line<T: NotesProvider>(
+    notes_provider: &T,
+    stdout: &mut RawTerminal<Stdout>,
+    events: &mut Events,
+    command_history: &mut Vec<String>,
+    status_bar: &mut StatusBar,
+) -> Result<Command> {
+    let notes = notes_provider.get_notes(&SortField::Name, &SortDir::Asc);
+    let note_names: Vec<String> = notes.iter().map(|note| note.name.clone()).collect();
+    let folder_names: Vec<String> = notes_provider
+        .get_folders()?
+        .iter()
+        .map(|folder| folder.name.clone())
+        .collect();
+    let tag_names: Vec<String> = count_tags(&notes)
+        .into_iter()
+        .map(|tag_count| tag_count.tag)
+        .collect();
+
+    let line = prompt_command(stdout, events, ":", command_history, |line| {
+        commands::complete(line, &note_names, &folder_names, &tag_names)
+    })?;
+    match line.map(|line| commands::parse(&line)) {
+        Some(Ok(command)) => Ok(command),
+        Some(Err(error)) => {
+            status_bar.flash(error.to_string());
+            Ok(Command::Noop)
+        }
+        None => Ok(Command::Noop),
     }
+}
+
+// Show every key binding and the command line subcommands over the screen drawn by
+// `draw_background`, until the user closes it.
+fn run_help(
+    stdout: &mut RawTerminal<Stdout>,
+    events: &mut Events,
+    keymap: &Keymap,
+    draw_background: impl Fn() -> String,
+) -> Result<()> {
+    let mut lines = keymap.get_help();
+    lines.push(String::new());
+    lines.push(String::from("Commands"));
+    lines.extend(commands::get_help());
+    lines.push(String::new());
+    lines.push(String::from("Command line subcommands"));
+    for subcommand in Args::command().get_subcommands() {
+        lines.push(format!(
+            "  {:<16}{}",
+            subcommand.get_name(),
+            subcommand
+                .get_about()
+                .map_or(String::new(), |about| about.to_string())
+        ));
+    }
+
+    let mut scroll = 0;
+    let mut key_buffer: Vec<Key> = vec![];
+    let mut last_keypress_time = Instant::now();
+    loop {
+        let terminal_height = termion::terminal_size().map_or(24, |(_, height)| height);
+        let visible_rows = overlay::get_visible_rows(lines.len(), terminal_height);
+        let last_scroll = lines.len() - visible_rows;
+        scroll = scroll.min(last_scroll);
+
+        write!(
+            stdout,
+            "{}{}",
+            draw_background(),
+            overlay::draw("Help", &lines, scroll)
+        )?;
+        stdout.flush()?;
+
+        let Some(Event::Key(key)) = events.next(None)? else {
+            continue;
+        };
+        let (action, count) = keymap.handle_key(key, &mut key_buffer, &mut last_keypress_time);
+        match action {
+            Action::Quit | Action::ClearFilter | Action::Help => break,
+            Action::NavDown => scroll += count.unwrap_or(1),
+            Action::NavUp => scroll = scroll.saturating_sub(count.unwrap_or(1)),
+            Action::HalfPageDown => {
+                scroll =
+                    scroll.saturating_add((visible_rows / 2).saturating_mul(count.unwrap_or(1)));
+            }
+            Action::HalfPageUp => {
+                scroll =
+                    scroll.saturating_sub((visible_rows / 2).saturating_mul(count.unwrap_or(1)));
+            }
+            Action::PageDown => {
+                scroll = scroll.saturating_add(visible_rows.saturating_mul(count.unwrap_or(1)));
+            }
+            Action::PageUp => {
+                scroll = scroll.saturating_sub(visible_rows.saturating_mul(count.unwrap_or(1)));
+            }
+            Action::NavTop => scroll = last_scroll,
+            Action::NavBottom => scroll = 0,
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+// List the notes in the trash until the user backs out with q or Esc. The rename key (r by default)
+// restores the note under the cursor and the delete key (dd) deletes it for good.
+fn run_trash<T: NotesProvider>(
+    notes_provider: &T,
+    pins: &Pins,
+    history: &mut UndoHistory,
+    stdout: &mut RawTerminal<Stdout>,
+    events: &mut Events,
+    status_bar: &mut StatusBar,
+    keymap: &Keymap,
+) -> Result<()> {
+    let columns = vec![
+        Column {
+            field: Field::Name,
+            name: "Name".to_string(),
+            sort_field: None,
+        },
+        Column {
+            field: Field::Size,
+            name: "Size".to_string(),
+            sort_field: None,
+        },
+        Column {
+            field: Field::Deleted,
+            name: "Deleted".to_string(),
+            sort_field: None,
+        },
+        Column {
+            field: Field::OriginalPath,
+            name: "Original Path".to_string(),
+            sort_field: None,
+        },
+    ];
+    status_bar.set_footer(format!(
+        "Trash. {}",
+        keymap.get_footer(&[
+            (Action::Rename, "Restore"),
+            (Action::Delete, "Delete forever"),
+            (Action::Quit, "Back"),
+        ])
+    ));
+
+    let mut state = NavigationState::new(0);
+    let mut key_buffer: Vec<Key> = vec![];
+    let mut last_keypress_time = Instant::now();
+    let mut entries = notes_provider.get_trash()?;
+    loop {
+        let rows: Vec<Rc<dyn Columnar>> = entries
+            .iter()
+            .map(|entry| entry.clone() as Rc<dyn Columnar>)
+            .collect();
+        state.set_list_size(rows.len() as u16);
+        state.fit_to_terminal();
+        write!(
+            stdout,
+            "{}",
+            table::draw(&rows, &columns, status_bar, &state, None)
+        )?;
+        stdout.flush()?;
+
+        let Some(Event::Key(key)) = events.next(status_bar.get_timeout())? else {
+            continue;
+        };
+
+        let (action, count) = keymap.handle_key(key, &mut key_buffer, &mut last_keypress_time);
+        if state.navigate(&action, count) {
+            continue;
+        }
+        match action {
+            Action::Quit | Action::ClearFilter => break,
+            // Notes in the trash can't be renamed, so the rename key restores them instead.
+            Action::Rename => {
+                if let Some(entry) = entries.get(state.get_selected_index()) {
+                    restore_note(entry, notes_provider, status_bar)?;
+                    entries = notes_provider.get_trash()?;
+                }
+            }
+            Action::Delete => {
+                if let Some(entry) = entries.get(state.get_selected_index()) {
+                    purge_note(
+                        entry,
+                        notes_provider,
+                        pins,
+                        history,
+                        stdout,
+                        events,
+                        status_bar,
+                    )?;
+                    entries = notes_provider.get_trash()?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+// List every tag with how many notes have it until the user backs out with q or Esc. Enter
+// returns the tag under the cursor, to filter the list by.
+fn run_tag_browser<T: NotesProvider>(
+    notes_provider: &T,
+    stdout: &mut RawTerminal<Stdout>,
+    events: &mut Events,
+    status_bar: &mut StatusBar,
+    keymap: &Keymap,
+) -> Result<Option<String>> {
+    let columns = vec![
+        Column {
+            field: Field::Tags,
+            name: "Tag".to_string(),
+            sort_field: None,
+        },
+        Column {
+            field: Field::Count,
+            name: "Notes".to_string(),
+            sort_field: None,
+        },
+    ];
+    status_bar.set_footer(format!(
+        "Tags. {}",
+        keymap.get_footer(&[(Action::OpenEditor, "Show notes"), (Action::Quit, "Back")])
+    ));
+
+    let notes = notes_provider.get_notes(&SortField::Name, &SortDir::Asc);
+    let tag_counts: Vec<Rc<TagCount>> = count_tags(&notes).into_iter().map(Rc::new).collect();
+    let rows: Vec<Rc<dyn Columnar>> = tag_counts
+        .iter()
+        .map(|tag_count| tag_count.clone() as Rc<dyn Columnar>)
+        .collect();
+
+    let mut state = NavigationState::new(0);
+    state.set_list_size(rows.len() as u16);
+    let mut key_buffer: Vec<Key> = vec![];
+    let mut last_keypress_time = Instant::now();
+    loop {
+        state.fit_to_terminal();
+        write!(
+            stdout,
+            "{}",
+            table::draw(&rows, &columns, status_bar, &state, None)
+        )?;
+        stdout.flush()?;
+
+        let Some(Event::Key(key)) = events.next(status_bar.get_timeout())? else {
+            continue;
+        };
+        let (action, count) = keymap.handle_key(key, &mut key_buffer, &mut last_keypress_time);
+        if state.navigate(&action, count) {
+            continue;
+        }
+        match action {
+            Action::Quit | Action::ClearFilter | Action::TagBrowser => return Ok(None),
+            Action::OpenEditor => {
+                if let Some(tag_count) = tag_counts.get(state.get_selected_index()) {
+                    return Ok(Some(tag_count.tag.clone()));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Let the user pick what a new note starts from until they back out with q or Esc, which returns
+// None. The first row is the default template for the note's extension, which is Some(None).
+fn run_template_picker(
+    templates: Vec<TemplateEntry>,
+    stdout: &mut RawTerminal<Stdout>,
+    events: &mut Events,
+    status_bar: &mut StatusBar,
+    keymap: &Keymap,
+) -> Result<Option<Option<PathBuf>>> {
+    let columns = vec![Column {
+        field: Field::Name,
+        name: "Template".to_string(),
+        sort_field: None,
+    }];
+    status_bar.set_footer(format!(
+        "New note. {}",
+        keymap.get_footer(&[
+            (Action::OpenEditor, "Use template"),
+            (Action::Quit, "Cancel")
+        ])
+    ));
+
+    let default = TemplateEntry {
+        name: String::from("(default)"),
+        path: None,
+    };
+    let templates: Vec<Rc<TemplateEntry>> = std::iter::once(default)
+        .chain(templates)
+        .map(Rc::new)
+        .collect();
+    let rows: Vec<Rc<dyn Columnar>> = templates
+        .iter()
+        .map(|template| template.clone() as Rc<dyn Columnar>)
+        .collect();
+
+    let mut state = NavigationState::new(0);
+    state.set_list_size(rows.len() as u16);
+    let mut key_buffer: Vec<Key> = vec![];
+    let mut last_keypress_time = Instant::now();
+    loop {
+        state.fit_to_terminal();
+        write!(
+            stdout,
+            "{}",
+            table::draw(&rows, &columns, status_bar, &state, None)
+        )?;
+        stdout.flush()?;
+
+        let Some(Event::Key(key)) = events.next(status_bar.get_timeout())? else {
+            continue;
+        };
+        let (action, count) = keymap.handle_key(key, &mut key_buffer, &mut last_keypress_time);
+        if state.navigate(&action, count) {
+            continue;
+        }
+        match action {
+            Action::Quit | Action::ClearFilter => return Ok(None),
+            Action::OpenEditor => {
+                if let Some(template) = templates.get(state.get_selected_index()) {
+                    return Ok(Some(template.path.clone()));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn get_search_results<T: NotesProvider>(
+    notes_provider: &T,
+    query: &str,
+    state: &NavigationState,
+) -> Result<Vec<Rc<SearchResult>>> {
+    let notes = notes_provider.get_notes(state.get_sort_field(), state.get_sort_dir());
+    Ok(search_notes(&notes, notes_provider, query)?
+        .into_iter()
+        .map(Rc::new)
+        .collect())
+}
+
+// The folders and notes in the folder being shown, folders first. With a tag filter it's the
+// matching notes anywhere under the folder instead.
+fn get_filtered_notes<T: NotesProvider>(
+    notes_provider: &T,
+    state: &NavigationState,
+    locks: &NoteLocks,
+    pins: &Pins,
+) -> Vec<Rc<NoteEntry>> {
+    if !state.get_tag_filter().is_empty() {
+        return pins.annotate(get_tagged_notes(notes_provider, state, locks));
+    }
+
+    let (mut note_list, mut folders) = notes_provider
+        .get_notes_and_folders(state.get_sort_field(), state.get_sort_dir())
+        .unwrap_or_else(|error| {
+            error!("error reading notes and folders: {}", error);
+            (vec![], vec![])
+        });
+    folders.retain(|folder| folder.get_folder() == state.get_folder());
+    sort_notes(&mut folders, state.get_sort_field(), state.get_sort_dir());
+    note_list.retain(|note| note.get_folder() == state.get_folder());
+
+    let mut note_list: Vec<Rc<NoteEntry>> = folders.into_iter().chain(note_list).collect();
+    if !state.get_filter().is_empty() {
+        note_list.retain(|note| find_matches(state.get_filter(), &note.get_file_name()).is_some());
+    }
+    pins.annotate(locks.annotate(note_list))
+}
+
+fn get_tagged_notes<T: NotesProvider>(
+    notes_provider: &T,
+    state: &NavigationState,
+    locks: &NoteLocks,
+) -> Vec<Rc<NoteEntry>> {
+    let expression = match TagExpression::parse(state.get_tag_filter()) {
+        Ok(expression) => expression,
+        Err(error) => {
+            error!("invalid tag filter {}: {}", state.get_tag_filter(), error);
+            return vec![];
+        }
+    };
+
+    let mut note_list = notes_provider.get_notes(state.get_sort_field(), state.get_sort_dir());
+    note_list.retain(|note| {
+        note.relative_path.starts_with(state.get_folder()) && expression.matches(&note.tags)
+    });
+    if !state.get_filter().is_empty() {
+        note_list.retain(|note| find_matches(state.get_filter(), &note.get_file_name()).is_some());
+    }
+    let note_list = note_list
+        .into_iter()
+        .map(|note| {
+            Rc::new(NoteEntry {
+                show_folder: true,
+                ..(*note).clone()
+            })
+        })
+        .collect();
+    locks.annotate(note_list)
+}
+
+// Open the folder a note is in and select it. The filters are cleared if they'd hide it.
+fn go_to_note<T: NotesProvider>(
+    notes_provider: &T,
+    state: &mut NavigationState,
+    locks: &NoteLocks,
+    pins: &Pins,
+    path: &Path,
+) {
+    state.set_folder(path.parent().unwrap_or(Path::new("")).to_path_buf());
+    state.clear_filter();
+    let mut note_list = get_filtered_notes(notes_provider, state, locks, pins);
+    if !note_list.iter().any(|note| note.relative_path == path) {
+        state.clear_tag_filter();
+        note_list = get_filtered_notes(notes_provider, state, locks, pins);
+    }
+    state.set_list_size(note_list.len() as u16);
+    let index = note_list.iter().position(|note| note.relative_path == path);
+    state.set_selected_index(index.unwrap_or(0));
+}
+
+// Folders can only be opened, everything else needs a note. Flashes a message saying so when a
+// folder is selected.
+fn get_selected_note<'a>(
+    note_list: &'a [Rc<NoteEntry>],
+    state: &NavigationState,
+    status_bar: &mut StatusBar,
+    action: &str,
+) -> Option<&'a Rc<NoteEntry>> {
+    let note = note_list.get(state.get_selected_index())?;
+    if note.is_folder {
+        status_bar.flash(format!("Folders can't be {}.", action));
+        return None;
+    }
+    Some(note)
+}
+
+// The notes a note action applies to: every marked note if there are any, otherwise the selected
+// one. An unfinished visual range is added to the marks first.
+fn get_target_notes<T: NotesProvider>(
+    notes_provider: &T,
+    note_list: &[Rc<NoteEntry>],
+    state: &mut NavigationState,
+    status_bar: &mut StatusBar,
+    action: &str,
+) -> Vec<Rc<NoteEntry>> {
+    finish_visual(state, note_list);
+    if state.get_marked().is_empty() {
+        return get_selected_note(note_list, state, status_bar, action)
+            .into_iter()
+            .cloned()
+            .collect();
+    }
+
+    // Marks can be in other folders, so look through every note.
+    notes_provider
+        .get_notes(state.get_sort_field(), state.get_sort_dir())
+        .into_iter()
+        .filter(|note| state.is_marked(&note.relative_path))
+        .collect()
+}
+
+// Marks go along with notes that are renamed or moved.
+fn move_marks(state: &mut NavigationState, operations: &[Operation]) {
+    for operation in operations {
+        if let Operation::Rename { from, to } = operation {
+            state.move_mark(Path::new(from), PathBuf::from(to));
+        }
+    }
+}
+
+// Unmark the notes a bulk action went through. Any it failed on or skipped stay marked, so it
+// can be tried again on just those.
+fn unmark_done(state: &mut NavigationState, operations: &[Operation]) {
+    for operation in operations {
+        match operation {
+            Operation::Rename { from: name, .. } | Operation::Delete { name, .. } => {
+                state.unmark(Path::new(name))
+            }
+            _ => {}
+        }
+    }
+}
+
+// Mark the notes in the visual range, if there is one, and leave visual mode.
+fn finish_visual(state: &mut NavigationState, note_list: &[Rc<NoteEntry>]) {
+    let Some((start, end)) = state.end_visual() else {
+        return;
+    };
+    for note in note_list.iter().take(end + 1).skip(start) {
+        if !note.is_folder {
+            state.mark(note.relative_path.clone());
+        }
+    }
+}
+
+// Check the locks on notes about to be changed, leaving out any the user doesn't want to touch.
+fn get_unlocked(
+    notes: Vec<Rc<NoteEntry>>,
+    locks: &NoteLocks,
+    stdout: &mut RawTerminal<Stdout>,
+    events: &mut Events,
+    action: &str,
+) -> Result<Vec<Rc<NoteEntry>>> {
+    let mut unlocked = vec![];
+    for note in notes {
+        if check_lock(&note, locks, stdout, events, action)? {
+            unlocked.push(note);
+        }
+    }
+    Ok(unlocked)
+}
+
+// Shown in place of the Name column's header, so it's clear which folder is open, what the tag
+// filter is and how many notes are marked.
+fn get_breadcrumb(state: &NavigationState) -> String {
+    let breadcrumb = state
+        .get_folder()
+        .components()
+        .fold(String::from("Notes"), |breadcrumb, folder| {
+            format!("{} › {}", breadcrumb, folder.as_os_str().to_string_lossy())
+        });
+    let breadcrumb = match state.get_tag_filter() {
+        "" => breadcrumb,
+        tag_filter => format!("{}  [#{}]", breadcrumb, tag_filter),
+    };
+    match (state.get_marked().len(), state.is_visual()) {
+        (_, true) => format!("{}  [Visual]", breadcrumb),
+        (0, false) => breadcrumb,
+        (marked, false) => format!("{}  [{} marked]", breadcrumb, marked),
+    }
+}
+
+// The calendar strip for the header, or None if there's no journal yet.
+fn get_journal_calendar<T: NotesProvider>(
+    notes_provider: &T,
+    note_list: &[Rc<NoteEntry>],
+    state: &NavigationState,
+    config: &Config,
+    journal_entries: &mut JournalEntries,
+) -> Option<CalendarStrip> {
+    let entries = journal_entries.get(notes_provider, config);
+    if entries.is_empty() {
+        return None;
+    }
+    let selected = note_list
+        .get(state.get_selected_index())
+        .and_then(|note| get_journal_date(config, note));
+    Some(get_calendar_strip(
+        entries,
+        selected,
+        Local::now().date_naive(),
+    ))
+}
+
+// Read just enough of the selected note to fill the preview pane, if it's open, along with the
+// notes linking to it.
+fn get_preview<T: NotesProvider>(
+    notes_provider: &T,
+    note_list: &[Rc<NoteEntry>],
+    state: &mut NavigationState,
+    config: &Config,
+    link_index: &mut LinkIndex,
+) -> Option<Preview> {
+    if !state.is_preview_open() {
+        return None;
+    }
+    let note = note_list.get(state.get_selected_index())?;
+
+    if note.is_folder {
+        // Nothing to read, so list what's inside instead.
+        let mut state = NavigationState::new(0);
+        state.set_folder(note.relative_path.clone());
+        let locks = NoteLocks::new(config);
+        let lines = get_filtered_notes(notes_provider, &state, &locks, &Pins::new(config))
+            .iter()
+            .map(|entry| match entry.is_folder {
+                true => format!("{}/", entry.get_file_name()),
+                false => entry.get_file_name(),
+            })
+            .collect();
+        return Some(Preview {
+            title: format!("{}/", note.name),
+            lines,
+            backlinks: vec![],
+            scroll: 0,
+            markdown: false,
+            ratio: config.get_preview_ratio(),
+        });
+    }
+
+    let height = state.get_window_size() as usize + 1;
+    let lines = notes_provider
+        .read_note_lines(note, state.get_preview_scroll() + height)
+        .unwrap_or_else(|error| {
+            error!("error reading preview of {}: {}", note.name, error);
+            vec![]
+        });
+
+    // Don't scroll past the end of the note.
+    if state.get_preview_scroll() >= lines.len() {
+        state.set_preview_scroll(lines.len().saturating_sub(1));
+    }
+
+    let notes = notes_provider.get_notes(&SortField::Name, &SortDir::Asc);
+    link_index.update(&notes, notes_provider);
+
+    Some(Preview {
+        title: note.name.clone(),
+        lines,
+        backlinks: link_index.get_backlinks(note, &notes),
+        scroll: state.get_preview_scroll(),
+        markdown: note
+            .path
+            .extension()
+            .is_some_and(|extension| extension == "md"),
+        ratio: config.get_preview_ratio(),
+    })
+}
+
+fn to_rows(note_list: &[Rc<NoteEntry>]) -> Vec<Rc<dyn Columnar>> {
+    note_list
+        .iter()
+        .map(|file| file.clone() as Rc<dyn Columnar>)
+        .collect()
+}
+
+fn get_footer(footer: &str, state: &NavigationState, keymap: &Keymap) -> String {
+    let footer = if state.is_preview_open() {
+        let scroll_footer = keymap.get_footer(&[
+            (Action::PreviewDown, "Scroll preview down"),
+            (Action::PreviewUp, "up"),
+        ]);
+        format!("{}; {}", scroll_footer, footer)
+    } else {
+        footer.to_string()
+    };
 
-    // If no chord is in progress, just process the single key.
-    match key_event {
-        Key::Char('j') => Action::NavDown,
-        Key::Char('k') => Action::NavUp,
-        Key::Char('G') => Action::NavTop,
-        Key::Char('q') => Action::Quit,
-        Key::Char('s') => Action::Sort,
-        Key::Char('r') => Action::Rename,
-        Key::Char('n') => Action::New,
-        Key::Char('\n') => Action::OpenEditor,
-        _ => Action::Noop,
+    if state.get_filter().is_empty() {
+        footer
+    } else {
+        let clear_keys = keymap
+            .describe(Action::ClearFilter)
+            .unwrap_or_else(|| String::from("unbound"));
+        format!(
+            "[/{} | {} to clear] {}",
+            state.get_filter(),
+            clear_keys,
+            footer
+        )
     }
 }
@@ -0,0 +1,197 @@
+// Just enough Markdown to make notes readable in the preview. Works a line at a time, so the only
+// state carried between lines is whether we're inside a fenced code block.
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Plain,
+    Heading,
+    Bold,
+    Italic,
+    Code,
+    Quote,
+    Marker,
+    Done,
+    Link,
+}
+
+// A run of text drawn in one style.
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+impl Span {
+    pub fn new(text: &str, style: Style) -> Self {
+        Span {
+            text: text.to_string(),
+            style,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Renderer {
+    in_code_block: bool,
+}
+
+impl Renderer {
+    pub fn render_line(&mut self, line: &str) -> Vec<Span> {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            self.in_code_block = !self.in_code_block;
+            return vec![Span::new(line, Style::Marker)];
+        }
+        if self.in_code_block {
+            return vec![Span::new(line, Style::Code)];
+        }
+
+        let indent = &line[..line.len() - trimmed.len()];
+
+        if let Some(heading) = get_heading(trimmed) {
+            return vec![Span::new(heading, Style::Heading)];
+        }
+
+        if let Some(quote) = trimmed.strip_prefix('>') {
+            let mut spans = vec![Span::new(&format!("{indent}│ "), Style::Marker)];
+            spans.extend(render_inline(quote.trim_start(), Style::Quote));
+            return spans;
+        }
+
+        if let Some((marker, item)) = get_list_item(trimmed) {
+            let mut spans = vec![Span::new(&format!("{indent}{marker}"), Style::Marker)];
+            if let Some(item) = item.strip_prefix("[ ] ") {
+                spans.push(Span::new("☐ ", Style::Marker));
+                spans.extend(render_inline(item, Style::Plain));
+            } else if let Some(item) = item
+                .strip_prefix("[x] ")
+                .or_else(|| item.strip_prefix("[X] "))
+            {
+                spans.push(Span::new("☑ ", Style::Marker));
+                spans.extend(render_inline(item, Style::Done));
+            } else {
+                spans.extend(render_inline(item, Style::Plain));
+            }
+            return spans;
+        }
+
+        render_inline(line, Style::Plain)
+    }
+}
+
+fn get_heading(line: &str) -> Option<&str> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    match &line[level..] {
+        "" => Some(""),
+        heading if heading.starts_with(' ') => Some(heading.trim()),
+        _ => None,
+    }
+}
+
+// Split a list item into the marker to draw and the rest of the line. Bullets are all drawn the
+// same, numbers are kept.
+fn get_list_item(line: &str) -> Option<(String, &str)> {
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(item) = line.strip_prefix(bullet) {
+            return Some((String::from("• "), item));
+        }
+    }
+
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 {
+        if let Some(item) = line[digits..].strip_prefix(". ") {
+            return Some((format!("{}. ", &line[..digits]), item));
+        }
+    }
+
+    None
+}
+
+// Emphasis, code spans and [[links]]. Markers without a closing pair are left as they are.
+fn render_inline(text: &str, base: Style) -> Vec<Span> {
+    let mut spans = vec![];
+    let mut plain = String::new();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        let styled = match c {
+            '`' => find_closing(rest, "`").map(|(inner, len)| (inner, len, Style::Code)),
+            '[' => find_link(rest).map(|len| (&rest[..len], len, Style::Link)),
+            '*' | '_' => {
+                let double = if c == '*' { "**" } else { "__" };
+                let single = &rest[..1];
+                // Underscores inside words are just underscores, e.g. snake_case.
+                let inside_word =
+                    c == '_' && plain.chars().last().is_some_and(char::is_alphanumeric);
+                if inside_word {
+                    None
+                } else if rest.starts_with(double) {
+                    find_closing(rest, double).map(|(inner, len)| (inner, len, Style::Bold))
+                } else {
+                    find_closing(rest, single).map(|(inner, len)| (inner, len, Style::Italic))
+                }
+            }
+            _ => None,
+        };
+
+        match styled {
+            Some((inner, len, style)) => {
+                if !plain.is_empty() {
+                    spans.push(Span::new(&plain, base));
+                    plain.clear();
+                }
+                spans.push(Span::new(inner, style));
+                rest = &rest[len..];
+            }
+            None => {
+                plain.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    if !plain.is_empty() {
+        spans.push(Span::new(&plain, base));
+    }
+    spans
+}
+
+// Plain text notes only get their [[links]] picked out.
+pub fn render_links(line: &str) -> Vec<Span> {
+    let mut spans = vec![];
+    let mut rest = line;
+    while let Some(start) = rest.find("[[") {
+        let Some(len) = find_link(&rest[start..]) else {
+            break;
+        };
+        if start > 0 {
+            spans.push(Span::new(&rest[..start], Style::Plain));
+        }
+        spans.push(Span::new(&rest[start..start + len], Style::Link));
+        rest = &rest[start + len..];
+    }
+    if !rest.is_empty() {
+        spans.push(Span::new(rest, Style::Plain));
+    }
+    spans
+}
+
+// The length of the [[link]] at the start of `text`, brackets included.
+fn find_link(text: &str) -> Option<usize> {
+    let inner = text.strip_prefix("[[")?;
+    let end = inner.find("]]")?;
+    (end > 0).then_some(end + 4)
+}
+
+// Find the text between `delimiter` at the start of `text` and the next one. Returns the inner
+// text and the length of the whole thing, delimiters included.
+fn find_closing<'a>(text: &'a str, delimiter: &str) -> Option<(&'a str, usize)> {
+    let start = delimiter.len();
+    let end = text[start..].find(delimiter)? + start;
+    if end == start {
+        return None;
+    }
+    Some((&text[start..end], end + delimiter.len()))
+}
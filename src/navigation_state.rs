@@ -1,4 +1,8 @@
-use log::debug;
+use crate::keys::Action;
+
+use log::{debug, warn};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 
 #[derive(Eq, PartialEq)]
 pub enum SortField {
@@ -20,24 +24,75 @@ pub struct NavigationState {
     sort_dir: SortDir,
     visible_window: (u16, u16),
     window_buffer: u16,
+    filter: String,
+    tag_filter: String,
+    terminal_height: u16,
+    preview: bool,
+    preview_scroll: usize,
+    folder: PathBuf,
+    marked: HashSet<PathBuf>,
+    visual_start: Option<usize>,
 }
 
 #[allow(dead_code)]
 impl NavigationState {
     pub fn new(selected_index: usize) -> Self {
-        // TODO fix error handling and make the visible window size adjust each render,
-        // so it handles terminal resizing.
-        let (_, h) = termion::terminal_size().unwrap();
-
-        let list_height = h - 2; // subtract 2 -- one for header, one for footer
-        NavigationState {
+        let mut state = NavigationState {
             selected_index,
             sort_field: SortField::Modified,
             sort_dir: SortDir::Desc,
-            visible_window: (0, list_height - 1), // subtract one since window is 0-based
+            visible_window: (0, 0),
             list_size: 0,
             window_buffer: 2,
+            filter: String::new(),
+            tag_filter: String::new(),
+            terminal_height: 0,
+            preview: false,
+            preview_scroll: 0,
+            folder: PathBuf::new(),
+            marked: HashSet::new(),
+            visual_start: None,
+        };
+        state.fit_to_terminal();
+        state
+    }
+
+    pub fn fit_to_terminal(&mut self) {
+        match termion::terminal_size() {
+            Ok((_, height)) => self.resize(height),
+            Err(error) => warn!("could not read terminal size: {}", error),
+        }
+    }
+
+    // Fit the visible window to a terminal `height` rows tall, keeping the selection in view.
+    pub fn resize(&mut self, height: u16) {
+        if height == self.terminal_height {
+            return;
+        }
+        self.terminal_height = height;
+
+        let list_height = height.saturating_sub(2).max(1); // subtract 2 -- one for header, one for footer
+        let visibility_range = list_height - 1; // subtract one since window is 0-based
+
+        let selected_index = self.selected_index as u16;
+        let mut window_start = self.visible_window.0;
+        if selected_index + self.window_buffer > window_start + visibility_range {
+            window_start = (selected_index + self.window_buffer).saturating_sub(visibility_range);
+        }
+        if selected_index < window_start + self.window_buffer {
+            window_start = selected_index.saturating_sub(self.window_buffer);
         }
+
+        // Don't leave empty rows at the bottom when the terminal grows.
+        window_start = window_start.min(self.list_size.saturating_sub(list_height));
+
+        debug!(
+            "resizing to height {} - vw:{},{}",
+            height,
+            window_start,
+            window_start + visibility_range
+        );
+        self.visible_window = (window_start, window_start + visibility_range);
     }
 
     pub fn get_list_size(&self) -> u16 {
@@ -96,6 +151,7 @@ impl NavigationState {
             );
 
             self.selected_index = new_index;
+            self.preview_scroll = 0;
         }
     }
 
@@ -119,11 +175,228 @@ impl NavigationState {
             new_index, self.selected_index, self.visible_window.0, self.visible_window.1
         );
 
+        if new_index != self.selected_index {
+            self.preview_scroll = 0;
+        }
         self.selected_index = new_index;
     }
 
+    // Carry out one of the movement actions shared by every list, repeated `count` times where
+    // that makes sense. With a count, the top and bottom keys go to that row instead, like vim's
+    // 5gg. Returns false if the action isn't a movement, so the caller can handle it.
+    pub fn navigate(&mut self, action: &Action, count: Option<usize>) -> bool {
+        let times = count.unwrap_or(1);
+        match action {
+            Action::NavDown => self.move_down(times),
+            Action::NavUp => self.move_up(times),
+            Action::NavTop => self.go_to(count.map_or(usize::MAX, |count| count - 1)),
+            Action::NavBottom => self.go_to(count.map_or(0, |count| count - 1)),
+            Action::HalfPageDown => {
+                self.move_down((self.get_page_size() / 2).saturating_mul(times))
+            }
+            Action::HalfPageUp => self.move_up((self.get_page_size() / 2).saturating_mul(times)),
+            Action::PageDown => self.move_down(self.get_page_size().saturating_mul(times)),
+            Action::PageUp => self.move_up(self.get_page_size().saturating_mul(times)),
+            Action::WindowTop => self.select_window_top(),
+            Action::WindowMiddle => self.select_window_middle(),
+            Action::WindowBottom => self.select_window_bottom(),
+            _ => return false,
+        }
+        true
+    }
+
+    // Move the selection down `rows`, stopping at the last row rather than not moving at all the
+    // way increment_selected_index does. For counts and paging, like 10j or Ctrl-d.
+    pub fn move_down(&mut self, rows: usize) {
+        let last_index = (self.list_size as usize).saturating_sub(1);
+        self.set_selected_index(self.selected_index.saturating_add(rows).min(last_index));
+    }
+
+    pub fn move_up(&mut self, rows: usize) {
+        self.decrement_selected_index(rows);
+    }
+
+    // Select the row at `index`, or the last row if the list is shorter than that.
+    pub fn go_to(&mut self, index: usize) {
+        let last_index = (self.list_size as usize).saturating_sub(1);
+        self.set_selected_index(index.min(last_index));
+    }
+
+    // How many rows fit on the screen.
+    pub fn get_page_size(&self) -> usize {
+        self.get_window_size() as usize + 1
+    }
+
+    // The first and last rows on the screen that hold something.
+    fn get_visible_rows(&self) -> (usize, usize) {
+        let last_index = (self.list_size as usize).saturating_sub(1);
+        (
+            self.visible_window.0 as usize,
+            (self.visible_window.1 as usize).min(last_index),
+        )
+    }
+
+    // Select the top, middle or bottom row on the screen, like vim's H, M and L. The top and
+    // bottom stay inside the window buffer so the list doesn't scroll, unless the list ends
+    // there.
+    pub fn select_window_top(&mut self) {
+        let (first, last) = self.get_visible_rows();
+        let index = match first {
+            0 => 0,
+            _ => first + self.window_buffer as usize,
+        };
+        self.set_selected_index(index.min(last));
+    }
+
+    pub fn select_window_middle(&mut self) {
+        let (first, last) = self.get_visible_rows();
+        self.set_selected_index((first + last) / 2);
+    }
+
+    pub fn select_window_bottom(&mut self) {
+        let (first, last) = self.get_visible_rows();
+        let index = if last + 1 >= self.list_size as usize {
+            last
+        } else {
+            last.saturating_sub(self.window_buffer as usize).max(first)
+        };
+        self.set_selected_index(index);
+    }
+
+    pub fn is_preview_open(&self) -> bool {
+        self.preview
+    }
+
+    pub fn toggle_preview(&mut self) {
+        self.preview = !self.preview;
+        self.preview_scroll = 0;
+    }
+
+    // The first line of the selected note shown in the preview. Resets whenever the selection
+    // moves.
+    pub fn get_preview_scroll(&self) -> usize {
+        self.preview_scroll
+    }
+
+    pub fn set_preview_scroll(&mut self, preview_scroll: usize) {
+        self.preview_scroll = preview_scroll;
+    }
+
+    // The folder being shown, relative to the notes directory. Empty for the top level.
+    pub fn get_folder(&self) -> &Path {
+        &self.folder
+    }
+
+    pub fn set_folder(&mut self, folder: PathBuf) {
+        self.folder = folder;
+        self.visual_start = None;
+    }
+
+    // Notes marked for a bulk action, by their path relative to the notes directory so the marks
+    // stay put when the list is re-sorted, filtered or another folder is opened.
+    pub fn is_marked(&self, path: &Path) -> bool {
+        self.marked.contains(path)
+    }
+
+    pub fn get_marked(&self) -> &HashSet<PathBuf> {
+        &self.marked
+    }
+
+    pub fn mark(&mut self, path: PathBuf) {
+        self.marked.insert(path);
+    }
+
+    pub fn unmark(&mut self, path: &Path) {
+        self.marked.remove(path);
+    }
+
+    // Keep a note marked when it's renamed or moved.
+    pub fn move_mark(&mut self, from: &Path, to: PathBuf) {
+        if self.marked.remove(from) {
+            self.marked.insert(to);
+        }
+    }
+
+    pub fn toggle_mark(&mut self, path: PathBuf) {
+        if !self.marked.remove(&path) {
+            self.marked.insert(path);
+        }
+    }
+
+    pub fn clear_marks(&mut self) {
+        self.marked.clear();
+        self.visual_start = None;
+    }
+
+    // Whether a bulk action would act on anything, counting an unfinished visual range.
+    pub fn has_marks(&self) -> bool {
+        !self.marked.is_empty() || self.visual_start.is_some()
+    }
+
+    // Visual mode covers every row between where it started and the selection, like vim's V.
+    pub fn start_visual(&mut self) {
+        self.visual_start = Some(self.selected_index);
+    }
+
+    pub fn is_visual(&self) -> bool {
+        self.visual_start.is_some()
+    }
+
+    pub fn is_in_visual_range(&self, index: usize) -> bool {
+        self.get_visual_range()
+            .is_some_and(|(start, end)| (start..=end).contains(&index))
+    }
+
+    fn get_visual_range(&self) -> Option<(usize, usize)> {
+        self.visual_start.map(|start| {
+            (
+                start.min(self.selected_index),
+                start.max(self.selected_index),
+            )
+        })
+    }
+
+    // Leave visual mode, returning the first and last rows it covered.
+    pub fn end_visual(&mut self) -> Option<(usize, usize)> {
+        let range = self.get_visual_range();
+        self.visual_start = None;
+        range
+    }
+
+    pub fn get_filter(&self) -> &str {
+        &self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: String) {
+        self.filter = filter;
+    }
+
+    pub fn clear_filter(&mut self) {
+        self.filter.clear();
+    }
+
+    // A tag expression, like `work AND NOT done`. Unlike the name filter it's kept when another
+    // folder is opened.
+    pub fn get_tag_filter(&self) -> &str {
+        &self.tag_filter
+    }
+
+    pub fn set_tag_filter(&mut self, tag_filter: String) {
+        self.tag_filter = tag_filter;
+    }
+
+    pub fn clear_tag_filter(&mut self) {
+        self.tag_filter.clear();
+    }
+
     pub fn set_list_size(&mut self, list_size: u16) {
         self.list_size = list_size;
+
+        // Keep the selection inside the list, e.g. when a filter narrows it down.
+        let last_index = (list_size as usize).saturating_sub(1);
+        if self.selected_index > last_index {
+            self.set_selected_index(last_index);
+        }
     }
 
     pub fn set_selected_index(&mut self, new_index: usize) {
@@ -133,6 +406,12 @@ impl NavigationState {
             self.decrement_selected_index(self.selected_index - new_index);
         }
     }
+
+    pub fn set_sort(&mut self, sort_field: SortField, sort_dir: SortDir) {
+        self.sort_field = sort_field;
+        self.sort_dir = sort_dir;
+    }
+
     pub fn sort(&mut self, sort_field: SortField) {
         let mut sort_dir = SortDir::Desc;
         if self.sort_field == sort_field {
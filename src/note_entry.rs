@@ -1,16 +1,30 @@
-use std::{path::PathBuf, time::SystemTime};
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
+use crate::lock::LockInfo;
 use crate::render::{Column, Columnar, Field};
 
-const DATE_FORMAT: &str = "%b %m %I:%M";
+pub const DATE_FORMAT: &str = "%b %m %I:%M";
 
+// A note, or a folder of notes. `name` is the note's path relative to the notes directory, e.g.
+// "work/todo.txt", which is also what users type to refer to it.
 #[derive(Clone)]
 pub struct NoteEntry {
     pub path: PathBuf,
+    pub relative_path: PathBuf,
     pub name: String,
     pub modified: SystemTime,
     pub is_default: bool,
+    pub is_pinned: bool,
+    pub is_folder: bool,
     pub size: u64,
+    pub lock: Option<LockInfo>,
+    // From the note's front matter.
+    pub tags: Vec<String>,
+    // Set for lists that span folders, like the tag filter's.
+    pub show_folder: bool,
 }
 
 impl NoteEntry {
@@ -23,34 +37,97 @@ impl NoteEntry {
     ) -> Self {
         NoteEntry {
             path,
+            relative_path: PathBuf::from(&name),
             name,
             modified,
             is_default,
+            is_pinned: false,
+            is_folder: false,
             size,
+            lock: None,
+            tags: vec![],
+            show_folder: false,
         }
     }
 
+    pub fn new_folder(path: PathBuf, name: String, modified: SystemTime) -> Self {
+        let mut folder = NoteEntry::new(path, name, modified, false, 0);
+        folder.is_folder = true;
+        folder
+    }
+
     pub fn get_size(&self) -> &u64 {
         &self.size
     }
+
+    // The folder the note is in, relative to the notes directory. Empty for the top level.
+    pub fn get_folder(&self) -> &Path {
+        self.relative_path.parent().unwrap_or(Path::new(""))
+    }
+
+    pub fn get_file_name(&self) -> String {
+        self.relative_path
+            .file_name()
+            .map_or(self.name.clone(), |name| name.to_string_lossy().to_string())
+    }
 }
 
 impl Columnar for NoteEntry {
     fn get_value(&self, column: &Column) -> String {
         match column.get_field() {
+            Field::Size if self.is_folder => String::new(),
             Field::Size => self.size.to_string(),
+            Field::Name if self.is_folder => format!("{}/", self.get_file_name()),
             Field::Name => {
+                // The list usually only shows one folder at a time, so the folder part of the
+                // name is left off.
                 let default_indicator = "  [Default]".to_owned();
+                let mut name = match self.show_folder {
+                    true => self.name.clone(),
+                    false => self.get_file_name(),
+                };
                 if self.is_default {
-                    format!("{}{}", self.name, default_indicator)
-                } else {
-                    self.name.to_string()
+                    name.push_str(&default_indicator);
+                }
+                if self.is_pinned {
+                    name.push_str("  [Pinned]");
                 }
+                match &self.lock {
+                    Some(lock) if lock.is_stale() => name.push_str("  [Stale lock]"),
+                    Some(_) => name.push_str("  [Locked]"),
+                    None => {}
+                }
+                name
             }
             Field::Modified => {
                 let date: chrono::DateTime<chrono::Local> = self.modified.into();
                 date.format(DATE_FORMAT).to_string()
             }
+            Field::Tags => self.tags.join(", "),
+            Field::Line | Field::Snippet | Field::Deleted | Field::OriginalPath | Field::Count => {
+                String::new()
+            }
         }
     }
+
+    fn get_mark_key(&self) -> Option<&Path> {
+        // Folders can't be marked.
+        match self.is_folder {
+            true => None,
+            false => Some(&self.relative_path),
+        }
+    }
+
+    fn get_filter_target(&self) -> Option<(String, usize)> {
+        // Notes listed with their folder still only match the filter on the file name.
+        let file_name = self.get_file_name();
+        let offset = match self.show_folder && !self.is_folder {
+            true => self
+                .name
+                .strip_suffix(&file_name)
+                .map_or(0, |folder| folder.chars().count()),
+            false => 0,
+        };
+        Some((file_name, offset))
+    }
 }
@@ -0,0 +1,166 @@
+use crate::config::Config;
+use crate::note_entry::NoteEntry;
+
+use anyhow::{Context, Result};
+use log::warn;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::SystemTime;
+use tempfile::NamedTempFile;
+use toml::{Table, Value};
+
+// The pinned notes, with the modified time and size of the pins file they were read at.
+type CachedPins = (SystemTime, u64, BTreeSet<String>);
+
+// Pinned notes, by name. They're kept in a file of their own rather than by the provider, so
+// pinning works the same wherever the notes are. Pins aren't dropped when a note is deleted, so
+// one that's restored from the trash or by undoing comes back pinned. They go once it's purged.
+pub struct Pins {
+    path: PathBuf,
+    // The list is annotated on every keypress, so the file is only read again once it changes,
+    // e.g. from a rename made on the command line.
+    cache: RefCell<Option<CachedPins>>,
+}
+
+impl Pins {
+    pub fn new(config: &Config) -> Self {
+        Pins {
+            path: PathBuf::from(config.get_pins_file()),
+            cache: RefCell::new(None),
+        }
+    }
+
+    fn read(&self) -> BTreeSet<String> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return BTreeSet::new(),
+            Err(error) => {
+                warn!("could not read the pins file: {}", error);
+                return BTreeSet::new();
+            }
+        };
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        if let Some((cached_modified, cached_size, pinned)) = &*self.cache.borrow() {
+            if *cached_modified == modified && *cached_size == metadata.len() {
+                return pinned.clone();
+            }
+        }
+
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(error) => {
+                warn!("could not read the pins file: {}", error);
+                return BTreeSet::new();
+            }
+        };
+        let pins = match contents.parse::<Table>() {
+            Ok(pins) => pins,
+            Err(error) => {
+                warn!("could not parse the pins file: {}", error);
+                return BTreeSet::new();
+            }
+        };
+        let pinned: BTreeSet<String> = pins
+            .get("pinned")
+            .and_then(|pinned| pinned.as_array())
+            .map(|pinned| {
+                pinned
+                    .iter()
+                    .filter_map(|name| Some(name.as_str()?.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        *self.cache.borrow_mut() = Some((modified, metadata.len(), pinned.clone()));
+        pinned
+    }
+
+    fn write(&self, pinned: &BTreeSet<String>) -> Result<()> {
+        let mut pins = Table::new();
+        pins.insert(
+            String::from("pinned"),
+            Value::Array(pinned.iter().cloned().map(Value::String).collect()),
+        );
+
+        // Written to a temporary file first, so a crash can't leave half a file behind.
+        let directory = self
+            .path
+            .parent()
+            .context("the pins file has no parent directory")?;
+        fs::create_dir_all(directory)?;
+        let mut file = NamedTempFile::new_in(directory)?;
+        std::io::Write::write_all(&mut file, pins.to_string().as_bytes())?;
+        let file = file.persist(&self.path)?;
+
+        // Two writes can land within the same tick of the modified time, so what was written is
+        // cached rather than left to be read back.
+        let metadata = file.metadata()?;
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        *self.cache.borrow_mut() = Some((modified, metadata.len(), pinned.clone()));
+        Ok(())
+    }
+
+    // Mark the pinned notes and move them to the top, keeping the order they were in otherwise.
+    pub fn annotate(&self, notes: Vec<Rc<NoteEntry>>) -> Vec<Rc<NoteEntry>> {
+        let pinned = self.read();
+        if pinned.is_empty() {
+            return notes;
+        }
+
+        let (pinned, unpinned): (Vec<Rc<NoteEntry>>, Vec<Rc<NoteEntry>>) = notes
+            .into_iter()
+            .map(|note| {
+                if note.is_folder || !pinned.contains(&note.name) {
+                    return note;
+                }
+                let mut pinned_note = (*note).clone();
+                pinned_note.is_pinned = true;
+                Rc::new(pinned_note)
+            })
+            .partition(|note| note.is_pinned);
+        pinned.into_iter().chain(unpinned).collect()
+    }
+
+    // Pin the note, or unpin it if it already is. Returns whether it's pinned now.
+    pub fn toggle(&self, note: &NoteEntry) -> Result<bool> {
+        let mut pinned = self.read();
+        let is_pinned = match pinned.remove(&note.name) {
+            true => false,
+            false => pinned.insert(note.name.clone()),
+        };
+        self.write(&pinned)
+            .context("could not write the pins file")?;
+        Ok(is_pinned)
+    }
+
+    // Keep a note pinned when it's renamed or moved. Failing to is only logged, since the rename
+    // itself went through.
+    pub fn rename(&self, from: &str, to: &str) {
+        let mut pinned = self.read();
+        if !pinned.remove(from) {
+            return;
+        }
+        pinned.insert(to.to_string());
+        if let Err(error) = self.write(&pinned) {
+            warn!(
+                "could not move the pin from {} to {}: {:#}",
+                from, to, error
+            );
+        }
+    }
+
+    // Unpin notes that are gone for good. Failing to is only logged, like a rename.
+    pub fn remove(&self, names: &[&str]) {
+        let mut pinned = self.read();
+        let before = pinned.len();
+        pinned.retain(|name| !names.contains(&name.as_str()));
+        if pinned.len() == before {
+            return;
+        }
+        if let Err(error) = self.write(&pinned) {
+            warn!("could not unpin {}: {:#}", names.join(", "), error);
+        }
+    }
+}
@@ -1,10 +1,9 @@
-use anyhow::{Context, Result};
-use std::io::{Stdout, Write};
-use std::{thread, time};
+use crate::events::{Event, Events};
+
+use anyhow::Result;
+use std::io::Write;
 use termion::cursor;
 use termion::event::Key;
-use termion::input::TermRead;
-use termion::raw::RawTerminal;
 
 pub fn clear<W: Write>(stdout: &mut W) -> Result<()> {
     write!(
@@ -18,53 +17,134 @@ pub fn clear<W: Write>(stdout: &mut W) -> Result<()> {
     Ok(())
 }
 
-pub fn prompt(
-    stdout: &mut RawTerminal<Stdout>,
-    stdin: &std::io::Stdin,
-    prompt_string: String,
-) -> Result<String> {
-    stdout.suspend_raw_mode()?;
-    clear(stdout)?;
-    write!(stdout, "{}", prompt_string)?;
-    let mut buffer = String::new();
+// Draw the prompt over the status bar on the bottom line, leaving the rest of the screen alone.
+fn draw_prompt<W: Write>(stdout: &mut W, prompt_string: &str, input: &str) -> Result<()> {
+    let (_, status_row) = termion::terminal_size()?;
+    write!(
+        stdout,
+        "{goto}{clear}{prompt_string}{input}",
+        goto = cursor::Goto(1, status_row),
+        clear = termion::clear::CurrentLine,
+    )?;
     stdout.flush()?;
-    stdin.read_line(&mut buffer)?;
-    let answer = buffer.trim().to_string();
-    stdout.activate_raw_mode()?;
 
-    Ok(answer)
+    Ok(())
+}
+
+// Read a single line of input in the status bar. Returns None if the prompt is cancelled with Esc.
+pub fn prompt<W: Write>(
+    stdout: &mut W,
+    events: &mut Events,
+    prompt_string: String,
+) -> Result<Option<String>> {
+    let mut input = String::new();
+    loop {
+        draw_prompt(stdout, &prompt_string, &input)?;
+
+        // On a resize only the prompt is drawn again, on the new bottom line. The rest of the
+        // screen stays as it was until the prompt is done.
+        let Some(Event::Key(key)) = events.next(None)? else {
+            continue;
+        };
+        match key {
+            Key::Char('\n') => return Ok(Some(input.trim().to_string())),
+            Key::Esc => return Ok(None),
+            Key::Backspace => {
+                input.pop();
+            }
+            Key::Char(c) => input.push(c),
+            _ => continue,
+        }
+    }
+}
+
+// A prompt for the command line. Up and Down go through `history`, which the entered line is
+// added to, and Tab cycles through the completions `complete` offers for the line so far.
+pub fn prompt_command<W: Write>(
+    stdout: &mut W,
+    events: &mut Events,
+    prompt_string: &str,
+    history: &mut Vec<String>,
+    complete: impl Fn(&str) -> Vec<String>,
+) -> Result<Option<String>> {
+    let mut input = String::new();
+    // Where we are in the history, and what was typed before going back through it.
+    let mut history_index = history.len();
+    let mut typed = String::new();
+    // The completions for the line as it was when Tab was first pressed, and which one is shown.
+    let mut completions: Option<(Vec<String>, usize)> = None;
+    loop {
+        draw_prompt(stdout, prompt_string, &input)?;
+
+        let Some(Event::Key(key)) = events.next(None)? else {
+            continue;
+        };
+        if !matches!(key, Key::Char('\t') | Key::BackTab) {
+            completions = None;
+        }
+        match key {
+            Key::Char('\n') => {
+                let input = input.trim().to_string();
+                if !input.is_empty() && history.last() != Some(&input) {
+                    history.push(input.clone());
+                }
+                return Ok(Some(input));
+            }
+            Key::Esc => return Ok(None),
+            Key::Char('\t') | Key::BackTab => {
+                let (candidates, index) = completions.get_or_insert_with(|| (complete(&input), 0));
+                if candidates.is_empty() {
+                    continue;
+                }
+                // The first Tab shows the first completion, later ones move through the rest.
+                if input == candidates[*index] {
+                    *index = match key {
+                        Key::BackTab => (*index + candidates.len() - 1) % candidates.len(),
+                        _ => (*index + 1) % candidates.len(),
+                    };
+                }
+                input = candidates[*index].clone();
+            }
+            Key::Up if history_index > 0 => {
+                if history_index == history.len() {
+                    typed = input.clone();
+                }
+                history_index -= 1;
+                input = history[history_index].clone();
+            }
+            Key::Down if history_index < history.len() => {
+                history_index += 1;
+                input = history.get(history_index).unwrap_or(&typed).clone();
+            }
+            Key::Backspace => {
+                input.pop();
+            }
+            Key::Char(c) => input.push(c),
+            _ => continue,
+        }
+    }
 }
 
-pub fn prompt_yesno(
-    stdout: &mut RawTerminal<Stdout>,
-    stdin: &std::io::Stdin,
+pub fn prompt_yesno<W: Write>(
+    stdout: &mut W,
+    events: &mut Events,
     prompt_string: String,
 ) -> Result<bool> {
-    clear(stdout)?;
-    write!(stdout, "{}", prompt_string)?;
-    stdout.flush()?;
+    loop {
+        draw_prompt(stdout, &prompt_string, "")?;
 
-    for event in stdin.keys() {
-        let key = event.with_context(|| "Error evaluating keystroke event")?;
+        let Some(Event::Key(key)) = events.next(None)? else {
+            continue;
+        };
         let value = match key {
             Key::Char('y') => true,
             Key::Char('Y') => true,
             Key::Char('n') => false,
             Key::Char('N') => false,
+            Key::Esc => false,
             _ => continue,
         };
 
         return Ok(value);
     }
-
-    Ok(false)
-}
-
-// Flash a warning for 1s. Useful in the case of a invalid prompt entry.
-pub fn flash_warning<W: Write>(stdout: &mut W, warning_text: String) -> Result<()> {
-    clear(stdout)?;
-    write!(stdout, "{}", warning_text)?;
-    stdout.flush()?;
-    thread::sleep(time::Duration::from_secs(1));
-    Ok(())
 }
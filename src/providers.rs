@@ -1,2 +1,3 @@
 pub mod file_system_provider;
 pub mod provider;
+pub mod sqlite_provider;
@@ -1,23 +1,183 @@
 use crate::config::Config;
 use crate::note_entry::NoteEntry;
+use crate::providers::provider::{sort_notes, NotesAndFolders};
+use crate::tags::{parse_tags, FRONT_MATTER_MAX_LINES};
+use crate::trash_entry::TrashEntry;
 use crate::NotesProvider;
 use crate::SortDir;
 use crate::SortField;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
+use log::error;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs;
+use std::io::{BufRead, BufReader, Write};
 use std::os::unix::fs::MetadataExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use toml::{Table, Value};
+
+// Deleted notes are moved here, next to a `.trashinfo` file recording where they came from and
+// when they were deleted. get_notes skips hidden folders, so the trash never shows up as a note.
+const TRASH_DIRECTORY: &str = ".trash";
+const TRASH_INFO_EXTENSION: &str = "trashinfo";
+
+// Tags read from a note's front matter, with the modified time and size they were read at.
+type CachedTags = (SystemTime, u64, Vec<String>);
 
 pub struct FileSystemNotesProvider<'a> {
     config: &'a Config,
+    // The list is read on every keypress, so front matter is only read again once a note changes.
+    tag_cache: RefCell<HashMap<PathBuf, CachedTags>>,
 }
 
 impl<'a> FileSystemNotesProvider<'a> {
-    pub fn new(config: &'a Config) -> FileSystemNotesProvider {
-        FileSystemNotesProvider { config }
+    pub fn new(config: &'a Config) -> FileSystemNotesProvider<'a> {
+        FileSystemNotesProvider {
+            config,
+            tag_cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn get_trash_directory(&self) -> PathBuf {
+        let mut trash_directory = PathBuf::from(self.config.get_notes_directory());
+        trash_directory.push(TRASH_DIRECTORY);
+        trash_directory
+    }
+
+    fn get_trash_info_path(&self, entry_id: &str) -> PathBuf {
+        let mut info_path = self.get_trash_directory();
+        info_path.push(format!("{}.{}", entry_id, TRASH_INFO_EXTENSION));
+        info_path
+    }
+
+    fn get_relative_name(&self, path: &Path) -> Option<String> {
+        let relative_path = path.strip_prefix(self.config.get_notes_directory()).ok()?;
+        Some(relative_path.to_str()?.to_owned())
+    }
+
+    fn get_tags(&self, path: &Path, modified: SystemTime, size: u64) -> Vec<String> {
+        let mut cache = self.tag_cache.borrow_mut();
+        if let Some((cached_modified, cached_size, tags)) = cache.get(path) {
+            if *cached_modified == modified && *cached_size == size {
+                return tags.clone();
+            }
+        }
+
+        let tags = match read_lines(path, FRONT_MATTER_MAX_LINES) {
+            Ok(lines) => parse_tags(&lines),
+            Err(error) => {
+                error!("error reading front matter of {:?}: {}", path, error);
+                vec![]
+            }
+        };
+        cache.insert(path.to_path_buf(), (modified, size, tags.clone()));
+        tags
+    }
+
+    // Walk the notes directory, returning every note and every folder. Hidden folders are
+    // skipped, which keeps the trash and lockfiles out of the list.
+    fn read_tree(&self) -> (Vec<Rc<NoteEntry>>, Vec<Rc<NoteEntry>>) {
+        let mut notes = vec![];
+        let mut folders = vec![];
+        let mut unvisited = vec![PathBuf::from(self.config.get_notes_directory())];
+        while let Some(directory) = unvisited.pop() {
+            let entries = match fs::read_dir(&directory) {
+                Ok(entries) => entries,
+                Err(error) => {
+                    error!("error reading folder {:?}: {}", directory, error);
+                    continue;
+                }
+            };
+
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let path = entry.path();
+                let (Ok(metadata), Some(name)) = (entry.metadata(), self.get_relative_name(&path))
+                else {
+                    continue;
+                };
+                let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+
+                if metadata.is_dir() {
+                    if entry.file_name().to_string_lossy().starts_with('.') {
+                        continue;
+                    }
+                    unvisited.push(path.clone());
+                    folders.push(Rc::new(NoteEntry::new_folder(path, name, modified)));
+                } else {
+                    let is_default = name == self.config.get_default_notes_file();
+                    let mut note =
+                        NoteEntry::new(path, name, modified, is_default, metadata.size());
+                    note.tags = self.get_tags(&note.path, modified, note.size);
+                    notes.push(Rc::new(note));
+                }
+            }
+        }
+
+        (notes, folders)
+    }
+
+    fn read_trash_info(&self, info_path: &Path) -> Result<TrashEntry> {
+        let info = fs::read_to_string(info_path)?.parse::<Table>()?;
+        let id = info_path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .context("invalid trash info file name")?
+            .to_owned();
+        let original_path = info
+            .get("original_path")
+            .and_then(|path| path.as_str())
+            .context("trash info is missing original_path")?;
+        let deleted = info
+            .get("deleted")
+            .and_then(|deleted| deleted.as_integer())
+            .context("trash info is missing deleted")?;
+
+        let mut trashed_path = self.get_trash_directory();
+        trashed_path.push(&id);
+        let name = self
+            .get_relative_name(Path::new(original_path))
+            .unwrap_or(id.clone());
+
+        Ok(TrashEntry::new(
+            id,
+            name,
+            PathBuf::from(original_path),
+            UNIX_EPOCH + Duration::from_secs(deleted.max(0) as u64),
+            fs::metadata(trashed_path).map_or(0, |metadata| metadata.size()),
+        ))
+    }
+}
+
+// Read up to `max_lines` lines from the top of a file, without reading the rest.
+fn read_lines(path: &Path, max_lines: usize) -> Result<Vec<String>> {
+    let file = fs::File::open(path).context("error opening note")?;
+    let mut reader = BufReader::new(file);
+    let mut lines = vec![];
+    let mut line = vec![];
+    while lines.len() < max_lines {
+        line.clear();
+        if reader
+            .read_until(b'\n', &mut line)
+            .context("error reading note")?
+            == 0
+        {
+            break;
+        }
+        let text = String::from_utf8_lossy(&line);
+        lines.push(text.trim_end_matches(['\r', '\n']).to_string());
+    }
+    Ok(lines)
+}
+
+// Notes can be created or moved into folders that don't exist yet.
+fn create_parent_folder(path: &Path) -> Result<()> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent).context("error creating folder"),
+        None => Ok(()),
     }
 }
 
@@ -40,6 +200,10 @@ impl<'a> NotesProvider for FileSystemNotesProvider<'a> {
         Ok(())
     }
 
+    fn stores_files(&self) -> bool {
+        true
+    }
+
     fn note_exists(&self, path: &Path) -> bool {
         // This might be more complicated in other providers. E.g. a sqlite database might get a
         // path and deconstruct it into a name or ID to check for existence in the DB.
@@ -47,73 +211,191 @@ impl<'a> NotesProvider for FileSystemNotesProvider<'a> {
     }
 
     fn delete_note(&self, note: &NoteEntry) -> Result<()> {
-        fs::remove_file(&note.path)?;
+        let trash_directory = self.get_trash_directory();
+        fs::create_dir_all(&trash_directory).context("error creating trash directory")?;
+
+        let deleted = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        // Prefix with the deletion time so the same name can be trashed more than once. Notes
+        // from different folders all end up in the one trash folder, so slashes are escaped.
+        let id = format!("{}_{}", deleted.as_millis(), note.name.replace('/', "%2F"));
+
+        let mut info = Table::new();
+        info.insert(
+            String::from("original_path"),
+            Value::String(note.path.to_string_lossy().to_string()),
+        );
+        info.insert(
+            String::from("deleted"),
+            Value::Integer(deleted.as_secs() as i64),
+        );
+        let info_path = self.get_trash_info_path(&id);
+        fs::write(&info_path, info.to_string()).context("error writing trash info")?;
+
+        let mut trashed_path = trash_directory;
+        trashed_path.push(&id);
+        if let Err(error) = fs::rename(&note.path, trashed_path) {
+            // Don't leave an entry behind for a note that never made it to the trash.
+            if let Err(error) = fs::remove_file(&info_path) {
+                error!("error removing trash info {:?}: {}", info_path, error);
+            }
+            return Err(error).context("error moving note to the trash");
+        }
+        Ok(())
+    }
+
+    fn get_trash(&self) -> Result<Vec<Rc<TrashEntry>>> {
+        let trash_directory = self.get_trash_directory();
+        if !trash_directory.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut entries = vec![];
+        for file in fs::read_dir(trash_directory)? {
+            let info_path = file?.path();
+            if info_path.extension().and_then(|ext| ext.to_str()) != Some(TRASH_INFO_EXTENSION) {
+                continue;
+            }
+            // A broken entry is skipped, so the rest of the trash can still be seen and purged.
+            match self.read_trash_info(&info_path) {
+                Ok(entry) => entries.push(Rc::new(entry)),
+                Err(error) => error!("error reading trash info {:?}: {:#}", info_path, error),
+            }
+        }
+
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.deleted));
+        Ok(entries)
+    }
+
+    fn restore_note(&self, entry: &TrashEntry) -> Result<()> {
+        if entry.original_path.exists() {
+            bail!(
+                "Cannot restore, a note already exists at {}",
+                entry.original_path.to_string_lossy()
+            );
+        }
+
+        let mut trashed_path = self.get_trash_directory();
+        trashed_path.push(&entry.id);
+        create_parent_folder(&entry.original_path)?;
+        fs::rename(trashed_path, &entry.original_path).context("error restoring note")?;
+        fs::remove_file(self.get_trash_info_path(&entry.id))
+            .context("error removing trash info")?;
+        Ok(())
+    }
+
+    fn purge_note(&self, entry: &TrashEntry) -> Result<()> {
+        let mut trashed_path = self.get_trash_directory();
+        trashed_path.push(&entry.id);
+        if trashed_path.exists() {
+            fs::remove_file(trashed_path).context("error purging note")?;
+        }
+        fs::remove_file(self.get_trash_info_path(&entry.id))
+            .context("error removing trash info")?;
         Ok(())
     }
 
     fn rename_note(&self, note: &NoteEntry, new_path: &Path) -> Result<bool> {
+        create_parent_folder(new_path)?;
         match fs::rename(&note.path, new_path) {
             Ok(_) => Ok(true),
             Err(error) => Err(error.into()),
         }
     }
 
+    fn clone_note(&self, note: &NoteEntry, new_path: &Path) -> Result<NoteEntry> {
+        create_parent_folder(new_path)?;
+        fs::copy(&note.path, new_path).context("error cloning note")?;
+        let metadata = fs::metadata(new_path)?;
+        let name = self
+            .get_relative_name(new_path)
+            .context("could not convert file name to string")?;
+        Ok(NoteEntry::new(
+            new_path.to_path_buf(),
+            name,
+            metadata.modified()?,
+            false,
+            metadata.size(),
+        ))
+    }
+
     fn create_note(&self, note: NoteEntry) -> Result<NoteEntry> {
+        create_parent_folder(&note.path)?;
         match fs::File::create(&note.path) {
             Ok(_) => Ok(note),
             Err(error) => Err(error).context("error creating note"),
         }
     }
 
+    fn read_note(&self, note: &NoteEntry) -> Result<String> {
+        fs::read_to_string(&note.path).context("error reading note")
+    }
+
+    fn read_note_lines(&self, note: &NoteEntry, max_lines: usize) -> Result<Vec<String>> {
+        read_lines(&note.path, max_lines)
+    }
+
+    fn write_note(&self, note: &NoteEntry, content: &str) -> Result<()> {
+        // A symlinked note is written where the link points, so the link stays a link.
+        let path = fs::canonicalize(&note.path).unwrap_or_else(|_| note.path.clone());
+        let metadata = fs::metadata(&path).ok();
+
+        // Renaming a new file into place would split a hardlinked note from its other names, so
+        // those are rewritten in place instead.
+        if metadata
+            .as_ref()
+            .is_some_and(|metadata| metadata.nlink() > 1)
+        {
+            let mut file = fs::File::create(&path).context("error opening note")?;
+            file.write_all(content.as_bytes())
+                .context("error writing note")?;
+            return file.sync_all().context("error writing note");
+        }
+
+        // Otherwise write to a temp file next to the note and rename it into place, so anything
+        // else reading the note (like an open editor) never sees it half written. The temp file
+        // gets the note's permissions, since it's created readable only by us.
+        let directory = path
+            .parent()
+            .context("could not find the note's directory")?;
+        let mut temp_file =
+            tempfile::NamedTempFile::new_in(directory).context("error creating temp file")?;
+        temp_file
+            .write_all(content.as_bytes())
+            .context("error writing note")?;
+        if let Some(metadata) = metadata {
+            fs::set_permissions(temp_file.path(), metadata.permissions())
+                .context("error copying the note's permissions")?;
+        }
+        temp_file
+            .persist(&path)
+            .context("error moving written note into place")?;
+        Ok(())
+    }
+
     fn get_notes(&self, sort_field: &SortField, sort_dir: &SortDir) -> Vec<Rc<NoteEntry>> {
-        let files = fs::read_dir(self.config.get_notes_directory()).unwrap();
-        let mut file_entries: Vec<Rc<NoteEntry>> = files
-            .filter(|entry| {
-                // Filter out directories
-                let file = entry.as_ref().unwrap();
-                !file.metadata().unwrap().is_dir()
-            })
-            .map(|entry| {
-                let file = entry.unwrap();
-                let name = file.file_name().to_str().unwrap().to_owned();
-                let path = file.path();
-                let is_default = name == self.config.get_default_notes_file();
-                Rc::new(NoteEntry::new(
-                    path,
-                    name,
-                    file.metadata().unwrap().modified().unwrap(),
-                    is_default,
-                    file.metadata().unwrap().size(),
-                ))
-            })
-            .collect();
-
-        file_entries.sort_by(|a, b| match sort_field {
-            SortField::Modified => {
-                let a_cmp = a.modified;
-                let b_cmp = b.modified;
-                match sort_dir {
-                    SortDir::Asc => a_cmp.cmp(&b_cmp),
-                    SortDir::Desc => b_cmp.cmp(&a_cmp),
-                }
-            }
-            SortField::Size => {
-                let a_cmp = a.get_size();
-                let b_cmp = b.get_size();
-                match sort_dir {
-                    SortDir::Asc => a_cmp.cmp(b_cmp),
-                    SortDir::Desc => b_cmp.cmp(a_cmp),
-                }
-            }
-            SortField::Name => {
-                let a_cmp = &a.name;
-                let b_cmp = &b.name;
-                match sort_dir {
-                    SortDir::Asc => a_cmp.cmp(b_cmp),
-                    SortDir::Desc => b_cmp.cmp(a_cmp),
-                }
-            }
-        });
-        file_entries
+        let (mut notes, _) = self.read_tree();
+        sort_notes(&mut notes, sort_field, sort_dir);
+        notes
+    }
+
+    fn get_folders(&self) -> Result<Vec<Rc<NoteEntry>>> {
+        let (_, folders) = self.read_tree();
+        Ok(folders)
+    }
+
+    fn get_notes_and_folders(
+        &self,
+        sort_field: &SortField,
+        sort_dir: &SortDir,
+    ) -> Result<NotesAndFolders> {
+        let (mut notes, folders) = self.read_tree();
+        sort_notes(&mut notes, sort_field, sort_dir);
+        Ok((notes, folders))
+    }
+
+    fn create_folder(&self, path: &Path) -> Result<()> {
+        fs::create_dir_all(path).context("error creating folder")
     }
 }
@@ -1,12 +1,69 @@
-use crate::{note_entry::NoteEntry, SortDir, SortField};
+use crate::{note_entry::NoteEntry, trash_entry::TrashEntry, SortDir, SortField};
 use anyhow::Result;
 use std::{path::Path, rc::Rc};
 
+// Every note and every folder, in that order.
+pub type NotesAndFolders = (Vec<Rc<NoteEntry>>, Vec<Rc<NoteEntry>>);
+
 pub trait NotesProvider {
     fn validate_default_note_exists(&self) -> Result<()>;
+    // Whether each note is a file at its path, which other programs can then be pointed at.
+    fn stores_files(&self) -> bool;
+    // Every note, including the ones in folders.
     fn get_notes(&self, sort_field: &SortField, sort_dir: &SortDir) -> Vec<Rc<NoteEntry>>;
+    // Every folder, at any depth, as entries with `is_folder` set.
+    fn get_folders(&self) -> Result<Vec<Rc<NoteEntry>>>;
+    // Both of the above, for listing a folder. Providers that find notes and folders the same way
+    // can do it in one pass.
+    fn get_notes_and_folders(
+        &self,
+        sort_field: &SortField,
+        sort_dir: &SortDir,
+    ) -> Result<NotesAndFolders> {
+        Ok((self.get_notes(sort_field, sort_dir), self.get_folders()?))
+    }
+    fn create_folder(&self, path: &Path) -> Result<()>;
     fn note_exists(&self, path: &Path) -> bool;
     fn create_note(&self, note: NoteEntry) -> Result<NoteEntry>;
     fn rename_note(&self, note: &NoteEntry, new_path: &Path) -> Result<bool>;
+    fn clone_note(&self, note: &NoteEntry, new_path: &Path) -> Result<NoteEntry>;
+    // Deleting only moves the note to the trash. It's gone for good once it's purged.
     fn delete_note(&self, note: &NoteEntry) -> Result<()>;
+    fn get_trash(&self) -> Result<Vec<Rc<TrashEntry>>>;
+    fn restore_note(&self, entry: &TrashEntry) -> Result<()>;
+    fn purge_note(&self, entry: &TrashEntry) -> Result<()>;
+    fn read_note(&self, note: &NoteEntry) -> Result<String>;
+    // Only the first `max_lines` lines, so previews don't have to load a whole large note.
+    fn read_note_lines(&self, note: &NoteEntry, max_lines: usize) -> Result<Vec<String>>;
+    fn write_note(&self, note: &NoteEntry, content: &str) -> Result<()>;
+}
+
+// Sorting is the same regardless of where the notes come from, so providers share this.
+pub fn sort_notes(notes: &mut [Rc<NoteEntry>], sort_field: &SortField, sort_dir: &SortDir) {
+    notes.sort_by(|a, b| match sort_field {
+        SortField::Modified => {
+            let a_cmp = a.modified;
+            let b_cmp = b.modified;
+            match sort_dir {
+                SortDir::Asc => a_cmp.cmp(&b_cmp),
+                SortDir::Desc => b_cmp.cmp(&a_cmp),
+            }
+        }
+        SortField::Size => {
+            let a_cmp = a.get_size();
+            let b_cmp = b.get_size();
+            match sort_dir {
+                SortDir::Asc => a_cmp.cmp(b_cmp),
+                SortDir::Desc => b_cmp.cmp(a_cmp),
+            }
+        }
+        SortField::Name => {
+            let a_cmp = &a.name;
+            let b_cmp = &b.name;
+            match sort_dir {
+                SortDir::Asc => a_cmp.cmp(b_cmp),
+                SortDir::Desc => b_cmp.cmp(a_cmp),
+            }
+        }
+    });
 }
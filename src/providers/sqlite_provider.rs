@@ -0,0 +1,378 @@
+use crate::config::Config;
+use crate::note_entry::NoteEntry;
+use crate::providers::provider::{sort_notes, NotesAndFolders};
+use crate::tags::{parse_tags, FRONT_MATTER_MAX_LINES};
+use crate::trash_entry::TrashEntry;
+use crate::NotesProvider;
+use crate::SortDir;
+use crate::SortField;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use log::error;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS notes (
+    name     TEXT PRIMARY KEY NOT NULL,
+    body     TEXT NOT NULL DEFAULT '',
+    modified INTEGER NOT NULL,
+    size     INTEGER NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS trash (
+    id       INTEGER PRIMARY KEY AUTOINCREMENT,
+    name     TEXT NOT NULL,
+    body     TEXT NOT NULL DEFAULT '',
+    modified INTEGER NOT NULL,
+    size     INTEGER NOT NULL DEFAULT 0,
+    deleted  INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS folders (
+    name     TEXT PRIMARY KEY NOT NULL,
+    modified INTEGER NOT NULL
+);";
+
+// How much of each body is read when listing notes, which is plenty for any front matter.
+const FRONT_MATTER_MAX_BYTES: i64 = 4096;
+
+// Stores an entire notebook in a single sqlite database file. Notes are keyed by their path
+// relative to the notes directory, so the rest of the app can keep passing paths around.
+pub struct SqliteNotesProvider<'a> {
+    config: &'a Config,
+    connection: Connection,
+}
+
+impl<'a> SqliteNotesProvider<'a> {
+    pub fn new(config: &'a Config) -> Result<SqliteNotesProvider<'a>> {
+        let connection = Connection::open(config.get_sqlite_database_path()).context(format!(
+            "error opening sqlite database {}",
+            config.get_sqlite_database_path()
+        ))?;
+        connection
+            .execute_batch(SCHEMA)
+            .context("error creating notes tables")?;
+
+        Ok(SqliteNotesProvider { config, connection })
+    }
+
+    fn note_key(&self, path: &Path) -> Result<String> {
+        let key = path
+            .strip_prefix(self.config.get_notes_directory())
+            .unwrap_or(path);
+        match key.to_str() {
+            Some(key) if !key.is_empty() => Ok(key.to_owned()),
+            _ => bail!("could not convert note path {:?} to a note name", path),
+        }
+    }
+
+    fn note_path(&self, key: &str) -> PathBuf {
+        let mut path = PathBuf::from(self.config.get_notes_directory());
+        path.push(key);
+        path
+    }
+
+    fn query_notes(&self) -> Result<Vec<Rc<NoteEntry>>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT name, modified, size, substr(body, 1, ?1) FROM notes")?;
+        // Only the top of each body is needed for its front matter.
+        let rows = statement.query_map([FRONT_MATTER_MAX_BYTES], |row| {
+            let name: String = row.get(0)?;
+            let modified: i64 = row.get(1)?;
+            let size: i64 = row.get(2)?;
+            let top: String = row.get(3)?;
+            Ok((name, modified, size, top))
+        })?;
+
+        let mut notes = vec![];
+        for row in rows {
+            let (name, modified, size, top) = row?;
+            let is_default = name == self.config.get_default_notes_file();
+            let mut note = NoteEntry::new(
+                self.note_path(&name),
+                name,
+                from_millis(modified),
+                is_default,
+                size as u64,
+            );
+            let lines: Vec<&str> = top.lines().take(FRONT_MATTER_MAX_LINES).collect();
+            note.tags = parse_tags(&lines);
+            notes.push(Rc::new(note));
+        }
+
+        Ok(notes)
+    }
+
+    fn query_folders(&self, notes: &[Rc<NoteEntry>]) -> Result<Vec<Rc<NoteEntry>>> {
+        // Folders only need a row of their own while they're empty. Any folder with notes in it
+        // is implied by the note names.
+        let mut folders: BTreeMap<String, SystemTime> = BTreeMap::new();
+        let mut statement = self
+            .connection
+            .prepare("SELECT name, modified FROM folders")?;
+        let rows = statement.query_map([], |row| {
+            let name: String = row.get(0)?;
+            let modified: i64 = row.get(1)?;
+            Ok((name, modified))
+        })?;
+        for row in rows {
+            let (name, modified) = row?;
+            folders.insert(name, from_millis(modified));
+        }
+
+        for note in notes {
+            for folder in note.relative_path.ancestors().skip(1) {
+                let Some(folder) = folder.to_str().filter(|folder| !folder.is_empty()) else {
+                    continue;
+                };
+                let modified = folders.entry(folder.to_owned()).or_insert(note.modified);
+                *modified = (*modified).max(note.modified);
+            }
+        }
+
+        Ok(folders
+            .into_iter()
+            .map(|(name, modified)| {
+                Rc::new(NoteEntry::new_folder(self.note_path(&name), name, modified))
+            })
+            .collect())
+    }
+}
+
+fn to_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+fn from_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+impl<'a> NotesProvider for SqliteNotesProvider<'a> {
+    fn validate_default_note_exists(&self) -> Result<()> {
+        // Unlike a notes directory, nobody is going to create the database rows by hand, so
+        // create the default note if it's missing instead of bailing.
+        let default_note = self.note_path(self.config.get_default_notes_file());
+        if !self.note_exists(&default_note) {
+            self.create_note(NoteEntry::new(
+                default_note,
+                self.config.get_default_notes_file().to_owned(),
+                SystemTime::now(),
+                true,
+                0,
+            ))?;
+        }
+
+        Ok(())
+    }
+
+    fn stores_files(&self) -> bool {
+        false
+    }
+
+    fn note_exists(&self, path: &Path) -> bool {
+        let Ok(key) = self.note_key(path) else {
+            return false;
+        };
+
+        self.connection
+            .query_row("SELECT 1 FROM notes WHERE name = ?1", [key], |_| Ok(()))
+            .optional()
+            .unwrap_or_else(|error| {
+                error!("error checking for note existence: {}", error);
+                None
+            })
+            .is_some()
+    }
+
+    fn delete_note(&self, note: &NoteEntry) -> Result<()> {
+        let key = self.note_key(&note.path)?;
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction
+            .execute(
+                "INSERT INTO trash (name, body, modified, size, deleted)
+                 SELECT name, body, modified, size, ?1 FROM notes WHERE name = ?2",
+                params![to_millis(SystemTime::now()), key],
+            )
+            .context("error moving note to the trash")?;
+        transaction
+            .execute("DELETE FROM notes WHERE name = ?1", [key])
+            .context("error deleting note")?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn get_trash(&self) -> Result<Vec<Rc<TrashEntry>>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT id, name, size, deleted FROM trash ORDER BY deleted DESC")?;
+        let rows = statement.query_map([], |row| {
+            let id: i64 = row.get(0)?;
+            let name: String = row.get(1)?;
+            let size: i64 = row.get(2)?;
+            let deleted: i64 = row.get(3)?;
+            Ok((id, name, size, deleted))
+        })?;
+
+        let mut entries = vec![];
+        for row in rows {
+            let (id, name, size, deleted) = row?;
+            entries.push(Rc::new(TrashEntry::new(
+                id.to_string(),
+                name.clone(),
+                self.note_path(&name),
+                from_millis(deleted),
+                size as u64,
+            )));
+        }
+
+        Ok(entries)
+    }
+
+    fn restore_note(&self, entry: &TrashEntry) -> Result<()> {
+        if self.note_exists(&entry.original_path) {
+            bail!("Cannot restore, a note named {} already exists", entry.name);
+        }
+
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction
+            .execute(
+                "INSERT INTO notes (name, body, modified, size)
+                 SELECT name, body, modified, size FROM trash WHERE id = ?1",
+                [&entry.id],
+            )
+            .context("error restoring note")?;
+        transaction
+            .execute("DELETE FROM trash WHERE id = ?1", [&entry.id])
+            .context("error removing note from the trash")?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn purge_note(&self, entry: &TrashEntry) -> Result<()> {
+        self.connection
+            .execute("DELETE FROM trash WHERE id = ?1", [&entry.id])
+            .context("error purging note")?;
+        Ok(())
+    }
+
+    fn rename_note(&self, note: &NoteEntry, new_path: &Path) -> Result<bool> {
+        let key = self.note_key(&note.path)?;
+        let new_key = self.note_key(new_path)?;
+        let updated = self
+            .connection
+            .execute(
+                "UPDATE notes SET name = ?1 WHERE name = ?2",
+                params![new_key, key],
+            )
+            .context("error renaming note")?;
+        Ok(updated > 0)
+    }
+
+    fn clone_note(&self, note: &NoteEntry, new_path: &Path) -> Result<NoteEntry> {
+        let key = self.note_key(&note.path)?;
+        let new_key = self.note_key(new_path)?;
+        let modified = SystemTime::now();
+        self.connection
+            .execute(
+                "INSERT INTO notes (name, body, modified, size)
+                 SELECT ?1, body, ?2, size FROM notes WHERE name = ?3",
+                params![new_key, to_millis(modified), key],
+            )
+            .context("error cloning note")?;
+        Ok(NoteEntry::new(
+            self.note_path(&new_key),
+            new_key,
+            modified,
+            false,
+            note.size,
+        ))
+    }
+
+    fn create_note(&self, note: NoteEntry) -> Result<NoteEntry> {
+        let key = self.note_key(&note.path)?;
+        self.connection
+            .execute(
+                "INSERT INTO notes (name, body, modified, size) VALUES (?1, '', ?2, 0)",
+                params![key, to_millis(note.modified)],
+            )
+            .context("error creating note")?;
+        Ok(note)
+    }
+
+    fn read_note(&self, note: &NoteEntry) -> Result<String> {
+        let key = self.note_key(&note.path)?;
+        self.connection
+            .query_row("SELECT body FROM notes WHERE name = ?1", [key], |row| {
+                row.get(0)
+            })
+            .context("error reading note")
+    }
+
+    fn read_note_lines(&self, note: &NoteEntry, max_lines: usize) -> Result<Vec<String>> {
+        // The body is a single column, so there's no reading part of it. It's already in memory
+        // once sqlite has the row anyway.
+        Ok(self
+            .read_note(note)?
+            .lines()
+            .take(max_lines)
+            .map(String::from)
+            .collect())
+    }
+
+    fn write_note(&self, note: &NoteEntry, content: &str) -> Result<()> {
+        let key = self.note_key(&note.path)?;
+        self.connection
+            .execute(
+                "UPDATE notes SET body = ?1, modified = ?2, size = ?3 WHERE name = ?4",
+                params![
+                    content,
+                    to_millis(SystemTime::now()),
+                    content.len() as i64,
+                    key
+                ],
+            )
+            .context("error writing note")?;
+        Ok(())
+    }
+
+    fn get_notes(&self, sort_field: &SortField, sort_dir: &SortDir) -> Vec<Rc<NoteEntry>> {
+        let mut notes = self.query_notes().unwrap_or_else(|error| {
+            error!("error reading notes from sqlite database: {}", error);
+            vec![]
+        });
+
+        sort_notes(&mut notes, sort_field, sort_dir);
+        notes
+    }
+
+    fn get_folders(&self) -> Result<Vec<Rc<NoteEntry>>> {
+        self.query_folders(&self.query_notes()?)
+    }
+
+    fn get_notes_and_folders(
+        &self,
+        sort_field: &SortField,
+        sort_dir: &SortDir,
+    ) -> Result<NotesAndFolders> {
+        let mut notes = self.query_notes()?;
+        let folders = self.query_folders(&notes)?;
+        sort_notes(&mut notes, sort_field, sort_dir);
+        Ok((notes, folders))
+    }
+
+    fn create_folder(&self, path: &Path) -> Result<()> {
+        let key = self.note_key(path)?;
+        self.connection
+            .execute(
+                "INSERT OR IGNORE INTO folders (name, modified) VALUES (?1, ?2)",
+                params![key, to_millis(SystemTime::now())],
+            )
+            .context("error creating folder")?;
+        Ok(())
+    }
+}
@@ -1,15 +1,22 @@
 use crate::navigation_state::SortField;
+use std::path::Path;
 
 pub enum Field {
     Size,
     Name,
     Modified,
+    Line,
+    Snippet,
+    Deleted,
+    OriginalPath,
+    Tags,
+    Count,
 }
 
 pub struct Column {
     pub field: Field,
     pub name: String,
-    pub sort_field: SortField,
+    pub sort_field: Option<SortField>,
 }
 
 impl Column {
@@ -21,21 +28,339 @@ impl Column {
         &self.name
     }
 
-    pub fn get_sort_field(&self) -> &SortField {
-        &self.sort_field
+    pub fn get_sort_field(&self) -> Option<&SortField> {
+        self.sort_field.as_ref()
     }
 }
 
 pub trait Columnar {
     fn get_value(&self, column: &Column) -> String;
+
+    // The path rows are marked by, for rows that can be marked.
+    fn get_mark_key(&self) -> Option<&Path> {
+        None
+    }
+
+    // The text the list filter matches against, and the character it starts at in the name
+    // cell, for rows the filter applies to.
+    fn get_filter_target(&self) -> Option<(String, usize)> {
+        None
+    }
+}
+
+// The selected note's content, drawn to the right of the table. `lines` runs from the top of the
+// note and only needs to cover what fits on screen below `scroll`. Markdown is rendered from the
+// top too, so a code block opened above the scroll position is still drawn as code.
+pub struct Preview {
+    pub title: String,
+    pub lines: Vec<String>,
+    // The notes linking to this one, listed under it.
+    pub backlinks: Vec<String>,
+    pub scroll: usize,
+    pub markdown: bool,
+    pub ratio: f64,
+}
+
+impl Preview {
+    // How many columns the table keeps on a terminal `terminal_width` columns wide.
+    pub fn get_table_width(&self, terminal_width: u16) -> u16 {
+        let preview_width = (terminal_width as f64 * self.ratio) as u16;
+        terminal_width.saturating_sub(preview_width)
+    }
+}
+
+pub mod preview {
+    use crate::markdown::{render_links, Renderer, Span, Style};
+    use crate::render::Preview;
+    use termion::{color, cursor, style};
+
+    // Split a line into rows at most `width` characters wide, breaking at spaces where possible.
+    // Each character keeps the style of the span it came from.
+    pub fn wrap(spans: &[Span], width: usize) -> Vec<Vec<(char, Style)>> {
+        let mut rows = vec![];
+        if width == 0 {
+            return rows;
+        }
+
+        // Tabs and control characters would throw off the layout, or worse, get interpreted by
+        // the terminal.
+        let line: Vec<(char, Style)> = spans
+            .iter()
+            .flat_map(|span| {
+                span.text
+                    .replace('\t', "    ")
+                    .chars()
+                    .filter(|c| !c.is_control())
+                    .map(|c| (c, span.style))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        let mut row = vec![];
+        for word in line.split_inclusive(|(c, _)| *c == ' ') {
+            let word_len = word.len() - usize::from(word.last().is_some_and(|(c, _)| *c == ' '));
+            if !row.is_empty() && row.len() + word_len > width {
+                rows.push(trim_end(row));
+                row = vec![];
+            }
+            for c in word {
+                // Words longer than a whole row get broken wherever they hit the edge.
+                if row.len() == width {
+                    rows.push(row);
+                    row = vec![];
+                }
+                row.push(*c);
+            }
+        }
+        rows.push(trim_end(row));
+        rows
+    }
+
+    fn trim_end(mut row: Vec<(char, Style)>) -> Vec<(char, Style)> {
+        while row.last().is_some_and(|(c, _)| *c == ' ') {
+            row.pop();
+        }
+        row
+    }
+
+    fn get_style_code(style: Style) -> String {
+        match style {
+            Style::Plain => String::new(),
+            Style::Heading => format!("{}{}", style::Bold, color::Fg(color::Cyan)),
+            Style::Bold => format!("{}", style::Bold),
+            Style::Italic => format!("{}", style::Italic),
+            Style::Code => format!("{}", color::Fg(color::Yellow)),
+            Style::Quote => format!("{}{}", style::Italic, color::Fg(color::LightBlack)),
+            Style::Marker => format!("{}", color::Fg(color::Blue)),
+            Style::Done => format!("{}", color::Fg(color::LightBlack)),
+            Style::Link => format!("{}{}", style::Underline, color::Fg(color::LightBlue)),
+        }
+    }
+
+    fn draw_row(row: &[(char, Style)]) -> String {
+        let mut row_str = String::new();
+        let mut current = Style::Plain;
+        for (c, style) in row {
+            if *style != current {
+                row_str = format!("{row_str}{}{}", style::Reset, get_style_code(*style));
+                current = *style;
+            }
+            row_str.push(*c);
+        }
+        format!("{row_str}{}", style::Reset)
+    }
+
+    // Draw the preview in the `width` columns starting at `left`, `height` rows tall below the
+    // title.
+    pub fn draw(preview: &Preview, left: u16, width: u16, height: u16) -> String {
+        // Leave room for the border and a space either side of it.
+        let text_width = width.saturating_sub(2) as usize;
+        let title: String = preview.title.chars().take(text_width).collect();
+        let mut preview_str = format!(
+            "{goto}{color}│ {title}{reset}",
+            goto = cursor::Goto(left, 1),
+            color = color::Fg(color::Yellow),
+            reset = color::Fg(color::Reset),
+        );
+
+        let mut renderer = Renderer::default();
+        let mut rows = preview
+            .lines
+            .iter()
+            .map(|line| {
+                if preview.markdown {
+                    renderer.render_line(line)
+                } else {
+                    render_links(line)
+                }
+            })
+            .skip(preview.scroll)
+            .flat_map(|spans| wrap(&spans, text_width));
+        let backlink_rows = get_backlink_rows(&preview.backlinks, height);
+        let note_height = height - backlink_rows.len() as u16;
+        for row_index in 0..note_height {
+            preview_str = format!(
+                "{preview_str}{goto}│ {row}",
+                goto = cursor::Goto(left, row_index + 2),
+                row = draw_row(&rows.next().unwrap_or_default()),
+            );
+        }
+        for (row_index, row) in backlink_rows.iter().enumerate() {
+            let row: String = row.chars().take(text_width).collect();
+            preview_str = format!(
+                "{preview_str}{goto}│ {color}{row}{reset}",
+                goto = cursor::Goto(left, note_height + row_index as u16 + 2),
+                color = color::Fg(color::Yellow),
+                reset = color::Fg(color::Reset),
+            );
+        }
+
+        preview_str
+    }
+
+    // The backlinks panel at the bottom of the preview, taking up to a third of its `height`.
+    fn get_backlink_rows(backlinks: &[String], height: u16) -> Vec<String> {
+        if backlinks.is_empty() {
+            return vec![];
+        }
+        let max_rows = (height as usize / 3).max(2);
+        let mut rows = vec![format!("Backlinks ({})", backlinks.len())];
+        if backlinks.len() < max_rows {
+            rows.extend(backlinks.iter().map(|name| format!("← {}", name)));
+        } else {
+            rows.extend(
+                backlinks
+                    .iter()
+                    .take(max_rows - 2)
+                    .map(|name| format!("← {}", name)),
+            );
+            rows.push(format!("  and {} more", backlinks.len() - (max_rows - 2)));
+        }
+        rows.truncate(height as usize);
+        rows
+    }
+}
+
+// A run of days for the header, marking the ones with a journal entry.
+pub struct CalendarStrip {
+    pub days: Vec<(chrono::NaiveDate, bool)>,
+    pub selected: Option<chrono::NaiveDate>,
+    pub today: chrono::NaiveDate,
+}
+
+pub mod calendar {
+    use crate::render::{table, CalendarStrip, Column, Columnar, Preview};
+    use chrono::Datelike;
+    use std::rc::Rc;
+    use termion::{color, cursor, style};
+
+    // Each day is its number, with the month's name before the first day and the 1st. Month
+    // names have no date.
+    fn get_cells(strip: &CalendarStrip) -> Vec<(String, Option<(chrono::NaiveDate, bool)>)> {
+        let mut cells = vec![];
+        for (index, (date, has_entry)) in strip.days.iter().enumerate() {
+            if index == 0 || date.day() == 1 {
+                cells.push((date.format("%b").to_string(), None));
+            }
+            cells.push((date.day().to_string(), Some((*date, *has_entry))));
+        }
+        cells
+    }
+
+    // Draw the strip at the right end of the header, if it fits after the table's columns.
+    pub fn draw(
+        strip: &CalendarStrip,
+        rows: &Vec<Rc<dyn Columnar>>,
+        columns: &[Column],
+        preview: Option<&Preview>,
+    ) -> String {
+        let cells = get_cells(strip);
+        let strip_width: usize = cells.iter().map(|(text, _)| text.chars().count() + 1).sum();
+
+        let terminal_width = termion::terminal_size().map_or(80, |(width, _)| width);
+        let table_width = match preview {
+            Some(preview) => preview.get_table_width(terminal_width),
+            None => terminal_width,
+        } as usize;
+        let columns_width: usize = columns
+            .iter()
+            .map(|column| table::get_column_width(rows, column))
+            .sum();
+        if columns_width + strip_width > table_width {
+            return String::new();
+        }
+
+        // Days with an entry stand out, the selected entry's day is inverted and today is
+        // underlined.
+        let mut strip_str = cursor::Goto((table_width - strip_width + 1) as u16, 1).to_string();
+        for (text, day) in cells {
+            let style_code = match day {
+                None => color::Fg(color::Yellow).to_string(),
+                Some((date, _)) if Some(date) == strip.selected => style::Invert.to_string(),
+                Some((_, true)) => format!("{}{}", style::Bold, color::Fg(color::Yellow)),
+                Some((_, false)) => color::Fg(color::LightBlack).to_string(),
+            };
+            let today = match day.is_some_and(|(date, _)| date == strip.today) {
+                true => style::Underline.to_string(),
+                false => String::new(),
+            };
+            strip_str = format!(
+                "{strip_str}{style_code}{today}{text}{reset} ",
+                reset = style::Reset,
+            );
+        }
+        strip_str
+    }
+}
+
+pub mod overlay {
+    use termion::{color, cursor};
+
+    // How many of `line_count` lines fit in the overlay on a terminal `terminal_height` rows tall.
+    pub fn get_visible_rows(line_count: usize, terminal_height: u16) -> usize {
+        // Leave a row above and below the box, plus one for each border.
+        line_count.min(terminal_height.saturating_sub(4) as usize)
+    }
+
+    // Draw a box in the middle of the screen, over whatever is already there, showing `lines`
+    // starting at `scroll`.
+    pub fn draw(title: &str, lines: &[String], scroll: usize) -> String {
+        let (terminal_width, terminal_height) = termion::terminal_size().unwrap_or((80, 24));
+
+        let content_width = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .chain([title.chars().count() + 2])
+            .max()
+            .unwrap_or(0);
+        let inner_width = content_width.min(terminal_width.saturating_sub(6) as usize);
+        let visible_rows = get_visible_rows(lines.len(), terminal_height);
+
+        let left = (terminal_width.saturating_sub(inner_width as u16 + 4) / 2).max(1);
+        let top = (terminal_height.saturating_sub(visible_rows as u16 + 2) / 2).max(1);
+
+        // Let the user know there's more to see when the help doesn't fit.
+        let more = if scroll + visible_rows < lines.len() {
+            "↓"
+        } else {
+            "─"
+        };
+        let title: String = title.chars().take(inner_width).collect();
+        let mut overlay_str = format!(
+            "{goto}{color}┌ {title} {border}┐",
+            goto = cursor::Goto(left, top),
+            color = color::Fg(color::Yellow),
+            border = "─".repeat(inner_width.saturating_sub(title.chars().count())),
+        );
+
+        for (row, line) in lines.iter().skip(scroll).take(visible_rows).enumerate() {
+            let line: String = line.chars().take(inner_width).collect();
+            overlay_str = format!(
+                "{overlay_str}{goto}│{reset} {line:<inner_width$} {color}│",
+                goto = cursor::Goto(left, top + 1 + row as u16),
+                reset = color::Fg(color::Reset),
+                color = color::Fg(color::Yellow),
+            );
+        }
+
+        format!(
+            "{overlay_str}{goto}└{border}{more}─┘{reset}",
+            goto = cursor::Goto(left, top + 1 + visible_rows as u16),
+            border = "─".repeat(inner_width),
+            reset = color::Fg(color::Reset),
+        )
+    }
 }
 
 pub mod table {
+    use crate::filter::find_matches;
+    use crate::render::{preview, Preview};
+    use crate::status_bar::StatusBar;
     use crate::NavigationState;
-    use crate::{Column, Columnar, SortDir};
+    use crate::{Column, Columnar, Field, SortDir};
     use log::debug;
     use std::rc::Rc;
-    use termion::{color, cursor};
+    use termion::{color, cursor, style};
 
     pub fn get_column_width(rows: &Vec<Rc<dyn Columnar>>, column: &Column) -> usize {
         let mut width = column.get_name().len() + 4;
@@ -48,10 +373,17 @@ pub mod table {
         width
     }
 
+    // Cut `value` down to `width` characters, or pad it out to `width` if it's shorter.
+    fn fit(value: &str, width: usize) -> String {
+        let value: String = value.chars().take(width).collect();
+        format!("{value:<width$}")
+    }
+
     pub fn draw_header(
         rows: &Vec<Rc<dyn Columnar>>,
         columns: &Vec<Column>,
         state: &NavigationState,
+        table_width: usize,
     ) -> String {
         let sort_indicator = match state.get_sort_dir() {
             SortDir::Desc => "↓",
@@ -65,45 +397,70 @@ pub mod table {
             color = color::Fg(color::Yellow),
         );
 
+        let mut remaining_width = table_width;
         for column in columns {
-            if *column.get_sort_field() == state.sort_field {
+            let width = get_column_width(rows, column).min(remaining_width);
+            remaining_width -= width;
+            if column.get_sort_field() == Some(&state.sort_field) {
                 header_str = format!(
-                    "{header_str}{value:<width$}",
-                    value = format!("{} {}", column.get_name(), sort_indicator),
-                    width = get_column_width(rows, column),
+                    "{header_str}{value}",
+                    value = fit(&format!("{} {}", column.get_name(), sort_indicator), width),
                 );
             } else {
-                header_str = format!(
-                    "{header_str}{value:<width$}",
-                    value = column.get_name(),
-                    width = get_column_width(rows, column),
-                );
+                header_str = format!("{header_str}{value}", value = fit(column.get_name(), width),);
             }
         }
 
         format!("{header_str}{reset}\n", reset = color::Fg(color::Reset))
     }
 
-    pub fn draw_footer(footer: &str, state: &NavigationState) -> String {
-        // Print the command prompt at the bottom of the terminal.
+    pub fn draw_footer(status_bar: &StatusBar, state: &NavigationState) -> String {
+        // Print the status bar at the bottom of the terminal.
         let window_size = state.get_window_size();
         let footer_render_index = window_size + 3;
         debug!("Rendering footer at position: {}", footer_render_index);
-        format!(
-            "{goto}{footer}",
-            goto = cursor::Goto(1, footer_render_index),
-            footer = footer
-        )
+        status_bar.draw(footer_render_index)
+    }
+
+    // Render a cell with the characters at `matches` highlighted. `row_color` restores the row's
+    // font color after each highlighted character.
+    fn draw_highlighted(value: &str, matches: &[usize], width: usize, row_color: &str) -> String {
+        let mut cell = String::new();
+        for (index, c) in value.chars().enumerate() {
+            if matches.contains(&index) {
+                cell = format!(
+                    "{cell}{bold}{highlight}{c}{reset_style}",
+                    bold = style::Bold,
+                    highlight = color::Fg(color::Red),
+                    reset_style = style::NoBold,
+                );
+                cell = format!("{cell}{row_color}");
+            } else {
+                cell.push(c);
+            }
+        }
+
+        let padding = width.saturating_sub(value.chars().count());
+        format!("{cell}{:padding$}", "")
     }
 
     pub fn draw(
         rows: &Vec<Rc<dyn Columnar>>,
         columns: &Vec<Column>,
-        footer: &str,
+        status_bar: &StatusBar,
         state: &NavigationState,
+        preview: Option<&Preview>,
     ) -> String {
         let (h1, h2) = state.get_visible_window();
 
+        // With a preview open the table only gets the left part of the screen, so rows are cut
+        // off before they run into it.
+        let terminal_width = termion::terminal_size().map_or(80, |(width, _)| width);
+        let table_width = match preview {
+            Some(preview) => preview.get_table_width(terminal_width),
+            None => terminal_width,
+        };
+
         let iter = IntoIterator::into_iter(rows);
         let mut render_index: u16 = 2;
         let mut table_str = String::new();
@@ -112,24 +469,45 @@ pub mod table {
                 continue;
             }
 
-            let mut row_str = String::new();
+            // Marked rows are drawn in yellow, and the selection gets a yellow background if it's
+            // marked too.
+            let marked = state.is_in_visual_range(index)
+                || row
+                    .get_mark_key()
+                    .is_some_and(|mark_key| state.is_marked(mark_key));
+            let row_color = match (state.get_selected_index() == index, marked) {
+                (true, false) => format!("{}{}", color::Bg(color::White), color::Fg(color::Black)),
+                (true, true) => format!("{}{}", color::Bg(color::Yellow), color::Fg(color::Black)),
+                (false, true) => color::Fg(color::Yellow).to_string(),
+                (false, false) => color::Fg(color::Reset).to_string(),
+            };
 
-            if state.get_selected_index() == index {
-                row_str = format!(
-                    "{highlight}{fontcolor}",
-                    highlight = color::Bg(color::White),
-                    fontcolor = color::Fg(color::Black),
-                );
-            }
-
-            row_str = format!("\r{row_str}");
+            let mut row_str = format!("\r{row_color}");
 
+            let mut remaining_width = table_width as usize;
             for column in columns {
-                row_str = format!(
-                    "{row_str}{value:<width$}",
-                    value = row.get_value(column),
-                    width = get_column_width(rows, column)
-                );
+                let width = get_column_width(rows, column).min(remaining_width);
+                remaining_width -= width;
+                let value: String = row.get_value(column).chars().take(width).collect();
+
+                let filter_target = match column.get_field() {
+                    Field::Name if !state.get_filter().is_empty() => row.get_filter_target(),
+                    _ => None,
+                };
+                if let Some((target, offset)) = filter_target {
+                    let matches: Vec<usize> = find_matches(state.get_filter(), &target)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|index| index + offset)
+                        .collect();
+                    row_str = format!(
+                        "{row_str}{value}",
+                        value = draw_highlighted(&value, &matches, width, &row_color),
+                    );
+                    continue;
+                }
+
+                row_str = format!("{row_str}{value}", value = fit(&value, width));
             }
 
             table_str = format!(
@@ -141,11 +519,21 @@ pub mod table {
             render_index = render_index.saturating_add(1);
         }
 
+        let preview_str = match preview {
+            Some(preview) => preview::draw(
+                preview,
+                table_width + 1,
+                terminal_width - table_width,
+                state.get_window_size() + 1,
+            ),
+            None => String::new(),
+        };
+
         format!(
-            "{header_str}{table_str}{footer}",
-            header_str = draw_header(rows, columns, state),
+            "{header_str}{table_str}{preview_str}{footer}",
+            header_str = draw_header(rows, columns, state, table_width as usize),
             table_str = table_str,
-            footer = draw_footer(footer, state),
+            footer = draw_footer(status_bar, state),
         )
     }
 }
@@ -0,0 +1,136 @@
+use crate::note_entry::NoteEntry;
+use crate::providers::provider::NotesProvider;
+use crate::render::{Column, Columnar, Field};
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use std::process::Command;
+use std::rc::Rc;
+
+const SNIPPET_LENGTH: usize = 80;
+
+pub struct SearchResult {
+    pub note: Rc<NoteEntry>,
+    pub line_number: usize,
+    pub line: String,
+}
+
+impl Columnar for SearchResult {
+    fn get_value(&self, column: &Column) -> String {
+        match column.get_field() {
+            Field::Name => self.note.name.to_string(),
+            Field::Line => self.line_number.to_string(),
+            Field::Snippet => self.line.trim().chars().take(SNIPPET_LENGTH).collect(),
+            _ => self.note.get_value(column),
+        }
+    }
+}
+
+// Search the content of every note for `query`, case-insensitively. If the provider keeps notes as
+// files they're handed to ripgrep when it's installed, otherwise they go through the built-in
+// scanner.
+pub fn search_notes<T: NotesProvider>(
+    notes: &[Rc<NoteEntry>],
+    notes_provider: &T,
+    query: &str,
+) -> Result<Vec<SearchResult>> {
+    // ripgrep would search the working directory if it were given no notes.
+    if query.is_empty() || notes.is_empty() {
+        return Ok(vec![]);
+    }
+
+    if notes_provider.stores_files() && ripgrep_available() {
+        return search_with_ripgrep(notes, query);
+    }
+
+    let mut results = vec![];
+    for note in notes {
+        results.append(&mut search_with_scanner(note, notes_provider, query));
+    }
+
+    Ok(results)
+}
+
+fn ripgrep_available() -> bool {
+    Command::new("rg").arg("--version").output().is_ok()
+}
+
+fn search_with_ripgrep(notes: &[Rc<NoteEntry>], query: &str) -> Result<Vec<SearchResult>> {
+    debug!("searching {} notes with ripgrep", notes.len());
+    let output = Command::new("rg")
+        .args([
+            "--null",
+            "--line-number",
+            "--with-filename",
+            "--no-heading",
+            "--color=never",
+            "--ignore-case",
+            "--fixed-strings",
+            "--regexp",
+            query,
+            "--",
+        ])
+        .args(notes.iter().map(|note| &note.path))
+        .output()
+        .context("failed to run ripgrep")?;
+
+    // rg exits 1 when nothing matched, anything above that is a real error.
+    if output.status.code().unwrap_or(2) > 1 {
+        warn!(
+            "ripgrep failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let mut results = vec![];
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        // Each line looks like `<path>\0<line number>:<text>`.
+        let Some((path, rest)) = line.split_once('\0') else {
+            continue;
+        };
+        let Some((line_number, text)) = rest.split_once(':') else {
+            continue;
+        };
+        let Some(note) = notes.iter().find(|note| note.path.as_os_str() == path) else {
+            continue;
+        };
+        let Ok(line_number) = line_number.parse() else {
+            continue;
+        };
+
+        results.push(SearchResult {
+            note: note.clone(),
+            line_number,
+            line: text.to_string(),
+        });
+    }
+
+    Ok(results)
+}
+
+fn search_with_scanner<T: NotesProvider>(
+    note: &Rc<NoteEntry>,
+    notes_provider: &T,
+    query: &str,
+) -> Vec<SearchResult> {
+    let query = query.to_lowercase();
+    let content = match notes_provider.read_note(note) {
+        Ok(content) => content,
+        Err(error) => {
+            // Binary files and the like shouldn't stop the rest of the search.
+            warn!("skipping note {} during search: {}", note.name, error);
+            return vec![];
+        }
+    };
+
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.to_lowercase().contains(&query))
+        .map(|(index, line)| SearchResult {
+            note: note.clone(),
+            line_number: index + 1,
+            line: line.to_string(),
+        })
+        .collect()
+}
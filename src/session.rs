@@ -0,0 +1,196 @@
+use crate::config::Config;
+use crate::navigation_state::{NavigationState, SortDir, SortField};
+use crate::note_entry::NoteEntry;
+use crate::providers::provider::NotesProvider;
+use crate::tags::TagExpression;
+
+use anyhow::{Context, Result};
+use log::warn;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use tempfile::NamedTempFile;
+use toml::{Table, Value};
+
+// The note that was selected when the last session ended. It's found again by name, since the
+// list may have changed in between.
+pub struct SavedSelection {
+    name: String,
+    index: usize,
+}
+
+impl SavedSelection {
+    // Where the note is in `note_list` now. If it's gone, whatever took its place is the closest
+    // thing to it.
+    pub fn find(&self, note_list: &[Rc<NoteEntry>]) -> usize {
+        note_list
+            .iter()
+            .position(|note| note.name == self.name)
+            .unwrap_or(self.index.min(note_list.len().saturating_sub(1)))
+    }
+}
+
+// The state file lives in the XDG state directory, since it's neither config nor notes.
+fn get_session_path() -> Option<PathBuf> {
+    let state_directory = match std::env::var_os("XDG_STATE_HOME") {
+        Some(directory) if !directory.is_empty() => PathBuf::from(directory),
+        _ => home::home_dir()?.join(".local/state"),
+    };
+    Some(state_directory.join("terminal_notes/state.toml"))
+}
+
+// Each notebook gets a session of its own in the state file, keyed by the provider and where it
+// keeps the notes, so switching configs doesn't lose anything.
+fn get_notebook(config: &Config) -> String {
+    match config.get_provider() {
+        "sqlite" => format!("sqlite:{}", config.get_sqlite_database_path()),
+        provider => format!("{}:{}", provider, config.get_notes_directory()),
+    }
+}
+
+// Every notebook's session. Anything else in the file, like a session saved before they were
+// kept apart, is dropped.
+fn read_sessions(path: &Path) -> Result<Table> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Table::new()),
+        Err(error) => {
+            return Err(error).with_context(|| format!("could not read the state file {:?}", path))
+        }
+    };
+    let mut sessions = contents
+        .parse::<Table>()
+        .with_context(|| format!("could not parse the state file {:?}", path))?;
+    sessions.retain(|_, session| session.is_table());
+    Ok(sessions)
+}
+
+// Save the sort, filters, folder, preview and selected note, for the next session to pick up.
+pub fn save_session(
+    state: &NavigationState,
+    note_list: &[Rc<NoteEntry>],
+    config: &Config,
+) -> Result<()> {
+    let path = get_session_path().context("could not find the home directory")?;
+
+    let mut session = Table::new();
+    let mut insert = |key: &str, value: Value| {
+        session.insert(String::from(key), value);
+    };
+    let sort_field = match state.get_sort_field() {
+        SortField::Name => "name",
+        SortField::Size => "size",
+        SortField::Modified => "modified",
+    };
+    insert("sort_field", Value::String(sort_field.to_string()));
+    let sort_dir = match state.get_sort_dir() {
+        SortDir::Asc => "asc",
+        SortDir::Desc => "desc",
+    };
+    insert("sort_dir", Value::String(sort_dir.to_string()));
+    insert("filter", Value::String(state.get_filter().to_string()));
+    insert(
+        "tag_filter",
+        Value::String(state.get_tag_filter().to_string()),
+    );
+    insert(
+        "folder",
+        Value::String(state.get_folder().to_string_lossy().to_string()),
+    );
+    insert("preview", Value::Boolean(state.is_preview_open()));
+    if let Some(note) = note_list.get(state.get_selected_index()) {
+        insert("selected", Value::String(note.name.clone()));
+        insert(
+            "selected_index",
+            Value::Integer(state.get_selected_index() as i64),
+        );
+    }
+
+    // A state file that can't be read is replaced rather than holding up saving this session.
+    let mut sessions = read_sessions(&path).unwrap_or_else(|error| {
+        warn!("{:#}", error);
+        Table::new()
+    });
+    sessions.insert(get_notebook(config), Value::Table(session));
+
+    // Written to a temporary file first, so a crash can't leave half a file behind.
+    let directory = path
+        .parent()
+        .context("the state file has no parent directory")?;
+    fs::create_dir_all(directory).context("could not create the state directory")?;
+    let mut file = NamedTempFile::new_in(directory)?;
+    std::io::Write::write_all(&mut file, sessions.to_string().as_bytes())?;
+    file.persist(&path)
+        .context("could not write the state file")?;
+    Ok(())
+}
+
+// Put `state` back the way the last session left it, returning the note that was selected so it
+// can be found once the list is loaded. Anything missing or no longer valid keeps its default.
+pub fn load_session<T: NotesProvider>(
+    state: &mut NavigationState,
+    notes_provider: &T,
+    config: &Config,
+) -> Option<SavedSelection> {
+    let path = get_session_path()?;
+    let mut sessions = match read_sessions(&path) {
+        Ok(sessions) => sessions,
+        Err(error) => {
+            warn!("{:#}", error);
+            return None;
+        }
+    };
+    let Some(Value::Table(session)) = sessions.remove(&get_notebook(config)) else {
+        return None;
+    };
+    let get = |key: &str| session.get(key).and_then(|value| value.as_str());
+
+    let sort_field = match get("sort_field") {
+        Some("name") => Some(SortField::Name),
+        Some("size") => Some(SortField::Size),
+        Some("modified") => Some(SortField::Modified),
+        _ => None,
+    };
+    let sort_dir = match get("sort_dir") {
+        Some("asc") => Some(SortDir::Asc),
+        Some("desc") => Some(SortDir::Desc),
+        _ => None,
+    };
+    if let (Some(sort_field), Some(sort_dir)) = (sort_field, sort_dir) {
+        state.set_sort(sort_field, sort_dir);
+    }
+
+    if let Some(filter) = get("filter") {
+        state.set_filter(filter.to_string());
+    }
+    if let Some(tag_filter) = get("tag_filter").filter(|tag| TagExpression::parse(tag).is_ok()) {
+        state.set_tag_filter(tag_filter.to_string());
+    }
+
+    // The folder may have been removed since.
+    if let Some(folder) = get("folder").filter(|folder| !folder.is_empty()) {
+        let folder = PathBuf::from(folder);
+        let exists = notes_provider
+            .get_folders()
+            .unwrap_or_default()
+            .iter()
+            .any(|existing| existing.relative_path == folder);
+        if exists {
+            state.set_folder(folder);
+        }
+    }
+
+    let preview = session.get("preview").and_then(|value| value.as_bool());
+    if preview == Some(true) && !state.is_preview_open() {
+        state.toggle_preview();
+    }
+
+    Some(SavedSelection {
+        name: get("selected")?.to_string(),
+        index: session
+            .get("selected_index")
+            .and_then(|index| index.as_integer())
+            .unwrap_or(0)
+            .max(0) as usize,
+    })
+}
@@ -0,0 +1,57 @@
+use std::time::{Duration, Instant};
+use termion::{color, cursor};
+
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(3);
+
+// The bottom line of the screen. Shows the footer, unless a transient message is being flashed.
+pub struct StatusBar {
+    footer: String,
+    message: Option<(String, Instant)>,
+}
+
+impl StatusBar {
+    pub fn new(footer: String) -> Self {
+        StatusBar {
+            footer,
+            message: None,
+        }
+    }
+
+    pub fn set_footer(&mut self, footer: String) {
+        self.footer = footer;
+    }
+
+    // Show a message in place of the footer for a few seconds, without blocking input.
+    pub fn flash(&mut self, message: String) {
+        self.message = Some((message, Instant::now() + MESSAGE_TIMEOUT));
+    }
+
+    // How long until the current message expires and the bar needs to be redrawn, if at all.
+    pub fn get_timeout(&self) -> Option<Duration> {
+        match &self.message {
+            Some((_, expires)) if *expires > Instant::now() => {
+                Some(expires.duration_since(Instant::now()))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn draw(&self, row: u16) -> String {
+        match self.get_timeout() {
+            Some(_) => format!(
+                "{goto}{clear}{color}{message}{reset}",
+                goto = cursor::Goto(1, row),
+                clear = termion::clear::CurrentLine,
+                color = color::Fg(color::Yellow),
+                message = self.message.as_ref().map_or("", |(message, _)| message),
+                reset = color::Fg(color::Reset),
+            ),
+            None => format!(
+                "{goto}{clear}{footer}",
+                goto = cursor::Goto(1, row),
+                clear = termion::clear::CurrentLine,
+                footer = self.footer,
+            ),
+        }
+    }
+}
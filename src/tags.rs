@@ -0,0 +1,429 @@
+use crate::note_entry::NoteEntry;
+use crate::render::{Column, Columnar, Field};
+
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use toml::Table;
+
+// Front matter has to close within this many lines, so listing notes only reads the top of each.
+pub const FRONT_MATTER_MAX_LINES: usize = 50;
+
+#[derive(PartialEq)]
+enum FrontMatter {
+    Yaml,
+    Toml,
+}
+
+// Find front matter at the very top of a note: a block between --- lines for YAML, or between
+// +++ lines for TOML. Returns its kind and the index of the closing line.
+fn find_front_matter<S: AsRef<str>>(lines: &[S]) -> Option<(FrontMatter, usize)> {
+    let (kind, delimiter) = match lines.first()?.as_ref().trim_end() {
+        "---" => (FrontMatter::Yaml, "---"),
+        "+++" => (FrontMatter::Toml, "+++"),
+        _ => return None,
+    };
+    let end = lines
+        .iter()
+        .take(FRONT_MATTER_MAX_LINES)
+        .skip(1)
+        .position(|line| {
+            let line = line.as_ref().trim_end();
+            line == delimiter || (kind == FrontMatter::Yaml && line == "...")
+        })?;
+    Some((kind, end + 1))
+}
+
+// The tags in a note's front matter, from the note's first lines. YAML can list them inline,
+// `tags: [work, oncall]` or `tags: work, oncall`, or as a block of `- work` lines. TOML uses
+// `tags = ["work", "oncall"]`.
+pub fn parse_tags<S: AsRef<str>>(lines: &[S]) -> Vec<String> {
+    let Some((kind, end)) = find_front_matter(lines) else {
+        return vec![];
+    };
+    let body: Vec<&str> = lines[1..end].iter().map(|line| line.as_ref()).collect();
+    let tags = match kind {
+        FrontMatter::Yaml => parse_yaml_tags(&body),
+        FrontMatter::Toml => parse_toml_tags(&body),
+    };
+
+    let mut unique: Vec<String> = vec![];
+    for tag in tags {
+        let tag = tag.trim().trim_start_matches('#').to_string();
+        if !tag.is_empty() && !unique.iter().any(|other| other.eq_ignore_ascii_case(&tag)) {
+            unique.push(tag);
+        }
+    }
+    unique
+}
+
+fn parse_yaml_tags(body: &[&str]) -> Vec<String> {
+    let Some(index) = body.iter().position(|line| line.starts_with("tags:")) else {
+        return vec![];
+    };
+    let value = body[index]["tags:".len()..].trim();
+    if !value.is_empty() {
+        return value
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .split(',')
+            .map(unquote)
+            .collect();
+    }
+
+    body[index + 1..]
+        .iter()
+        .map_while(|line| line.trim_start().strip_prefix("- "))
+        .map(unquote)
+        .collect()
+}
+
+fn parse_toml_tags(body: &[&str]) -> Vec<String> {
+    let Ok(front_matter) = body.join("\n").parse::<Table>() else {
+        return vec![];
+    };
+    match front_matter.get("tags") {
+        Some(toml::Value::Array(tags)) => tags
+            .iter()
+            .filter_map(|tag| tag.as_str().map(String::from))
+            .collect(),
+        Some(toml::Value::String(tag)) => vec![tag.clone()],
+        _ => vec![],
+    }
+}
+
+fn unquote(value: &str) -> String {
+    value.trim().trim_matches(['"', '\'']).to_string()
+}
+
+// Replace the tags in a note's front matter, adding YAML front matter if it has none.
+pub fn set_tags(content: &str, tags: &[String]) -> String {
+    let mut lines: Vec<String> = content.lines().map(String::from).collect();
+    match find_front_matter(&lines) {
+        Some((FrontMatter::Yaml, end)) => {
+            let start = lines[1..end]
+                .iter()
+                .position(|line| line.starts_with("tags:"));
+            let tags_line = format!("tags: [{}]", tags.join(", "));
+            match start {
+                Some(start) => {
+                    let start = start + 1;
+                    // A block list goes on until the first line that isn't one of its items.
+                    let block_length = lines[start + 1..end]
+                        .iter()
+                        .take_while(|line| line.trim_start().starts_with("- "))
+                        .count();
+                    lines.splice(start..=start + block_length, [tags_line]);
+                }
+                None => lines.insert(end, tags_line),
+            }
+        }
+        Some((FrontMatter::Toml, end)) => {
+            let tags_line = format!(
+                "tags = [{}]",
+                tags.iter()
+                    .map(|tag| toml::Value::String(tag.clone()).to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            let start = lines[1..end].iter().position(|line| {
+                line.split_once('=')
+                    .is_some_and(|(key, _)| key.trim() == "tags")
+            });
+            match start {
+                Some(start) => {
+                    let start = start + 1;
+                    // An array can be split over several lines, up to the one that closes it.
+                    // Anything else, like a single tag as a string, is on the one line.
+                    let value = lines[start].split_once('=').map_or("", |(_, value)| value);
+                    let length = match value.trim_start().starts_with('[') {
+                        true => lines[start..end]
+                            .iter()
+                            .position(|line| line.contains(']'))
+                            .unwrap_or(0),
+                        false => 0,
+                    };
+                    lines.splice(start..=start + length, [tags_line]);
+                }
+                None => lines.insert(end, tags_line),
+            }
+        }
+        None => {
+            lines.splice(
+                0..0,
+                [
+                    String::from("---"),
+                    format!("tags: [{}]", tags.join(", ")),
+                    String::from("---"),
+                ],
+            );
+        }
+    }
+
+    let mut new_content = lines.join("\n");
+    if content.ends_with('\n') || content.is_empty() {
+        new_content.push('\n');
+    }
+    new_content
+}
+
+// A filter on tags, like `work AND NOT done` or `(work OR home) urgent`. Terms next to each
+// other without an operator are ANDed.
+pub enum TagExpression {
+    Tag(String),
+    Not(Box<TagExpression>),
+    And(Box<TagExpression>, Box<TagExpression>),
+    Or(Box<TagExpression>, Box<TagExpression>),
+}
+
+impl TagExpression {
+    pub fn parse(text: &str) -> Result<Self> {
+        let spaced = text.replace('(', " ( ").replace(')', " ) ");
+        let tokens: Vec<&str> = spaced.split_whitespace().collect();
+        if tokens.is_empty() {
+            bail!("Enter a tag, or tags joined with AND, OR and NOT");
+        }
+
+        let mut position = 0;
+        let expression = parse_or(&tokens, &mut position)?;
+        if let Some(token) = tokens.get(position) {
+            bail!("Unexpected '{}' in tag filter", token);
+        }
+        Ok(expression)
+    }
+
+    // Tags are matched ignoring case.
+    pub fn matches(&self, tags: &[String]) -> bool {
+        match self {
+            TagExpression::Tag(tag) => tags.iter().any(|other| other.eq_ignore_ascii_case(tag)),
+            TagExpression::Not(expression) => !expression.matches(tags),
+            TagExpression::And(left, right) => left.matches(tags) && right.matches(tags),
+            TagExpression::Or(left, right) => left.matches(tags) || right.matches(tags),
+        }
+    }
+}
+
+fn is_keyword(token: &str, keyword: &str) -> bool {
+    token.eq_ignore_ascii_case(keyword)
+}
+
+fn parse_or(tokens: &[&str], position: &mut usize) -> Result<TagExpression> {
+    let mut expression = parse_and(tokens, position)?;
+    while tokens
+        .get(*position)
+        .is_some_and(|token| is_keyword(token, "or"))
+    {
+        *position += 1;
+        let right = parse_and(tokens, position)?;
+        expression = TagExpression::Or(Box::new(expression), Box::new(right));
+    }
+    Ok(expression)
+}
+
+fn parse_and(tokens: &[&str], position: &mut usize) -> Result<TagExpression> {
+    let mut expression = parse_not(tokens, position)?;
+    loop {
+        match tokens.get(*position) {
+            Some(token) if is_keyword(token, "and") => *position += 1,
+            Some(token) if is_keyword(token, "or") || *token == ")" => break,
+            Some(_) => {}
+            None => break,
+        }
+        let right = parse_not(tokens, position)?;
+        expression = TagExpression::And(Box::new(expression), Box::new(right));
+    }
+    Ok(expression)
+}
+
+fn parse_not(tokens: &[&str], position: &mut usize) -> Result<TagExpression> {
+    let Some(token) = tokens.get(*position) else {
+        bail!("Tag filter ends too soon");
+    };
+    *position += 1;
+    match *token {
+        token if is_keyword(token, "not") => {
+            Ok(TagExpression::Not(Box::new(parse_not(tokens, position)?)))
+        }
+        "(" => {
+            let expression = parse_or(tokens, position)?;
+            if tokens.get(*position) != Some(&")") {
+                bail!("Missing ')' in tag filter");
+            }
+            *position += 1;
+            Ok(expression)
+        }
+        token if token == ")" || is_keyword(token, "and") || is_keyword(token, "or") => {
+            bail!("Expected a tag before '{}'", token)
+        }
+        tag => Ok(TagExpression::Tag(tag.trim_start_matches('#').to_string())),
+    }
+}
+
+// A row in the tag browser.
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+}
+
+impl Columnar for TagCount {
+    fn get_value(&self, column: &Column) -> String {
+        match column.get_field() {
+            Field::Tags => self.tag.clone(),
+            Field::Count => self.count.to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+// Every tag used in `notes` with how many notes have it, in alphabetical order. Tags that only
+// differ in case are counted together.
+pub fn count_tags(notes: &[Rc<NoteEntry>]) -> Vec<TagCount> {
+    let mut counts: BTreeMap<String, TagCount> = BTreeMap::new();
+    for tag in notes.iter().flat_map(|note| note.tags.iter()) {
+        counts
+            .entry(tag.to_lowercase())
+            .or_insert_with(|| TagCount {
+                tag: tag.clone(),
+                count: 0,
+            })
+            .count += 1;
+    }
+    counts.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn matches(expression: &str, names: &[&str]) -> bool {
+        TagExpression::parse(expression)
+            .expect("expression should parse")
+            .matches(&tags(names))
+    }
+
+    fn parse_error(expression: &str) -> String {
+        TagExpression::parse(expression)
+            .err()
+            .expect("expression should not parse")
+            .to_string()
+    }
+
+    fn round_trip(content: &str, new_tags: &[&str]) -> Vec<String> {
+        let content = set_tags(content, &tags(new_tags));
+        let lines: Vec<&str> = content.lines().collect();
+        parse_tags(&lines)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert!(matches("a OR b AND c", &["a"]));
+        assert!(matches("a OR b AND c", &["b", "c"]));
+        assert!(!matches("a OR b AND c", &["b"]));
+    }
+
+    #[test]
+    fn terms_without_an_operator_are_anded() {
+        assert!(matches("work urgent", &["work", "urgent"]));
+        assert!(!matches("work urgent", &["work"]));
+    }
+
+    #[test]
+    fn not_applies_to_the_next_term_only() {
+        assert!(matches("NOT done AND work", &["work"]));
+        assert!(!matches("NOT done AND work", &["work", "done"]));
+        assert!(!matches("NOT done AND work", &[]));
+        assert!(matches("NOT NOT work", &["work"]));
+    }
+
+    #[test]
+    fn parentheses_group_terms() {
+        assert!(matches("(a OR b) AND c", &["b", "c"]));
+        assert!(!matches("(a OR b) AND c", &["a"]));
+        assert!(matches("NOT (a OR b)", &["c"]));
+        assert!(!matches("NOT (a OR b)", &["b"]));
+    }
+
+    #[test]
+    fn keywords_and_tags_ignore_case() {
+        assert!(matches("Work and not DONE", &["work"]));
+        assert!(matches("#work", &["WORK"]));
+    }
+
+    #[test]
+    fn bad_expressions_are_errors() {
+        assert_eq!(
+            parse_error("  "),
+            "Enter a tag, or tags joined with AND, OR and NOT"
+        );
+        assert_eq!(parse_error("work AND"), "Tag filter ends too soon");
+        assert_eq!(parse_error("(work OR home"), "Missing ')' in tag filter");
+        assert_eq!(parse_error("work )"), "Unexpected ')' in tag filter");
+        assert_eq!(parse_error("OR work"), "Expected a tag before 'OR'");
+    }
+
+    #[test]
+    fn set_tags_replaces_yaml_tags() {
+        let content = "---\ntitle: x\ntags: [old]\n---\nbody\n";
+        assert_eq!(
+            round_trip(content, &["work", "oncall"]),
+            tags(&["work", "oncall"])
+        );
+        assert_eq!(
+            set_tags(content, &tags(&["work"])),
+            "---\ntitle: x\ntags: [work]\n---\nbody\n"
+        );
+    }
+
+    #[test]
+    fn set_tags_replaces_a_yaml_block_list() {
+        let content = "---\ntags:\n  - old\n  - older\ntitle: x\n---\nbody";
+        assert_eq!(
+            set_tags(content, &tags(&["new"])),
+            "---\ntags: [new]\ntitle: x\n---\nbody"
+        );
+    }
+
+    #[test]
+    fn set_tags_replaces_toml_tags() {
+        let content = "+++\ntags = [\n  \"old\",\n]\ntitle = \"x\"\n+++\nbody\n";
+        assert_eq!(
+            round_trip(content, &["work", "done"]),
+            tags(&["work", "done"])
+        );
+        assert_eq!(
+            set_tags(content, &tags(&["work"])),
+            "+++\ntags = [\"work\"]\ntitle = \"x\"\n+++\nbody\n"
+        );
+    }
+
+    #[test]
+    fn set_tags_replaces_a_single_toml_tag() {
+        let content = "+++\ntags = \"old\"\ntitle = \"x\"\ndraft = true\n+++\nbody [1]\n";
+        assert_eq!(
+            set_tags(content, &tags(&["new"])),
+            "+++\ntags = [\"new\"]\ntitle = \"x\"\ndraft = true\n+++\nbody [1]\n"
+        );
+    }
+
+    #[test]
+    fn set_tags_escapes_toml_strings() {
+        let content = "+++\ntitle = \"x\"\n+++\n";
+        assert_eq!(
+            round_trip(content, &["quote\"d", "back\\slash", "escape\u{1b}"]),
+            tags(&["quote\"d", "back\\slash", "escape\u{1b}"])
+        );
+    }
+
+    #[test]
+    fn set_tags_adds_front_matter_to_notes_without_any() {
+        assert_eq!(
+            set_tags("body\n", &tags(&["work"])),
+            "---\ntags: [work]\n---\nbody\n"
+        );
+        assert_eq!(round_trip("", &["a", "b"]), tags(&["a", "b"]));
+    }
+}
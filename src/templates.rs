@@ -0,0 +1,154 @@
+use crate::config::Config;
+use crate::render::{Column, Columnar, Field};
+
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDate, NaiveDateTime, NaiveTime};
+use std::fmt::{Display, Write};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+// What the {{placeholders}} in a template are filled in with.
+struct Placeholders {
+    // The new note's file name without its extension.
+    title: String,
+    date: NaiveDate,
+    time: NaiveTime,
+}
+
+// A template with its placeholders filled in.
+pub struct Filled {
+    pub content: String,
+    // The line {{cursor}} was on, counting from 1, for the editor to start at.
+    pub cursor: Option<usize>,
+}
+
+// Fill in a template's placeholders: {{title}}, {{uuid}}, {{cursor}}, and {{date}} and {{time}},
+// which can be given a strftime format like {{date:%A %e %B}}. Anything else in braces is left as
+// it is.
+fn fill(template: &str, placeholders: &Placeholders) -> Filled {
+    let mut filled = String::with_capacity(template.len());
+    let mut cursor = None;
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let inside = rest[start + 2..start + 2 + length].trim();
+        filled.push_str(&rest[..start]);
+        if inside == "cursor" {
+            // Only the first one counts, the rest are just dropped.
+            cursor = cursor.or(Some(filled.matches('\n').count() + 1));
+        } else {
+            match get_value(inside, placeholders) {
+                Some(value) => filled.push_str(&value),
+                None => filled.push_str(&rest[start..start + length + 4]),
+            }
+        }
+        rest = &rest[start + length + 4..];
+    }
+    filled.push_str(rest);
+    Filled {
+        content: filled,
+        cursor,
+    }
+}
+
+fn get_value(placeholder: &str, placeholders: &Placeholders) -> Option<String> {
+    let (name, format) = match placeholder.split_once(':') {
+        Some((name, format)) => (name.trim(), Some(format)),
+        None => (placeholder, None),
+    };
+    let datetime = NaiveDateTime::new(placeholders.date, placeholders.time);
+    match name {
+        "title" => Some(placeholders.title.clone()),
+        "uuid" => Some(Uuid::new_v4().to_string()),
+        "date" => format_checked(datetime.format(format.unwrap_or("%Y-%m-%d"))),
+        "time" => format_checked(datetime.format(format.unwrap_or("%H:%M"))),
+        _ => None,
+    }
+}
+
+// None if `format` isn't a valid date format, rather than panicking like to_string would.
+pub fn format_date(date: NaiveDate, format: &str) -> Option<String> {
+    format_checked(date.format(format))
+}
+
+// The same for anything chrono formats, e.g. a timestamp.
+pub fn format_checked(formatted: impl Display) -> Option<String> {
+    let mut checked = String::new();
+    write!(checked, "{}", formatted).ok()?;
+    Some(checked)
+}
+
+// A row in the template picker. The first one has no path and stands for the default template
+// for the new note's extension, or none at all.
+pub struct TemplateEntry {
+    pub name: String,
+    pub path: Option<PathBuf>,
+}
+
+impl Columnar for TemplateEntry {
+    fn get_value(&self, column: &Column) -> String {
+        match column.get_field() {
+            Field::Name => self.name.clone(),
+            _ => String::new(),
+        }
+    }
+}
+
+// Every file in the templates directory, by name. Empty if there isn't one.
+pub fn get_templates(config: &Config) -> Vec<TemplateEntry> {
+    let Ok(entries) = fs::read_dir(config.get_templates_directory()) else {
+        return vec![];
+    };
+    let mut templates: Vec<TemplateEntry> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+        .map(|entry| TemplateEntry {
+            name: entry.file_name().to_string_lossy().to_string(),
+            path: Some(entry.path()),
+        })
+        .collect();
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    templates
+}
+
+// What a new note at `path` starts with: the template given, or else the default for its
+// extension, filled in. Empty if there's neither.
+pub fn get_new_note_content(
+    config: &Config,
+    path: &Path,
+    template: Option<&Path>,
+) -> Result<Filled> {
+    match get_template_path(config, path, template) {
+        Some(template) => fill_template(&template, path, Local::now().date_naive()),
+        None => Ok(Filled {
+            content: String::new(),
+            cursor: None,
+        }),
+    }
+}
+
+// Template names are looked up in the templates directory.
+fn get_template_path(config: &Config, path: &Path, template: Option<&Path>) -> Option<PathBuf> {
+    let template = match template {
+        Some(template) => template,
+        None => {
+            let extension = path.extension()?.to_str()?;
+            Path::new(config.get_default_template(extension)?)
+        }
+    };
+    Some(Path::new(config.get_templates_directory()).join(template))
+}
+
+// Read a template and fill it in for a new note at `path`, dated `date`.
+pub fn fill_template(template: &Path, path: &Path, date: NaiveDate) -> Result<Filled> {
+    let template = fs::read_to_string(template)
+        .with_context(|| format!("could not read the template {}", template.display()))?;
+    let title = path
+        .file_stem()
+        .map_or(String::new(), |stem| stem.to_string_lossy().to_string());
+    let time = Local::now().time();
+    Ok(fill(&template, &Placeholders { title, date, time }))
+}
@@ -0,0 +1,49 @@
+use std::{path::PathBuf, time::SystemTime};
+
+use crate::note_entry::DATE_FORMAT;
+use crate::render::{Column, Columnar, Field};
+
+// A deleted note sitting in the trash. `id` is whatever the provider needs to find it again.
+#[derive(Clone)]
+pub struct TrashEntry {
+    pub id: String,
+    pub name: String,
+    pub original_path: PathBuf,
+    pub deleted: SystemTime,
+    pub size: u64,
+}
+
+impl TrashEntry {
+    pub fn new(
+        id: String,
+        name: String,
+        original_path: PathBuf,
+        deleted: SystemTime,
+        size: u64,
+    ) -> Self {
+        TrashEntry {
+            id,
+            name,
+            original_path,
+            deleted,
+            size,
+        }
+    }
+}
+
+impl Columnar for TrashEntry {
+    fn get_value(&self, column: &Column) -> String {
+        match column.get_field() {
+            Field::Name => self.name.to_string(),
+            Field::Size => self.size.to_string(),
+            Field::Deleted => {
+                let date: chrono::DateTime<chrono::Local> = self.deleted.into();
+                date.format(DATE_FORMAT).to_string()
+            }
+            Field::OriginalPath => self.original_path.to_string_lossy().to_string(),
+            Field::Modified | Field::Line | Field::Snippet | Field::Tags | Field::Count => {
+                String::new()
+            }
+        }
+    }
+}
@@ -0,0 +1,604 @@
+use crate::actions::{find_note, get_new_note_path, rename_keeping_pin};
+use crate::config::Config;
+use crate::note_entry::NoteEntry;
+use crate::providers::provider::NotesProvider;
+
+use anyhow::{bail, Context, Result};
+use log::warn;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tempfile::NamedTempFile;
+use toml::{Table, Value};
+
+// Older steps are dropped so the journal doesn't grow forever.
+const MAX_STEPS: usize = 100;
+
+// A change to a single note, with what's needed to reverse it. Notes are referred to by name,
+// relative to the notes directory.
+pub enum Operation {
+    // A note that didn't exist before, from being created or cloned. Its content is kept once
+    // it's undone, so redoing can bring it back.
+    Create {
+        name: String,
+        content: Option<String>,
+    },
+    Delete {
+        name: String,
+        content: String,
+    },
+    // Renames, moves and archiving.
+    Rename {
+        from: String,
+        to: String,
+    },
+    // Changing a note's tags, which rewrites its front matter.
+    Retag {
+        name: String,
+        before: String,
+        after: String,
+    },
+    // Pointing a note's links at a note that was renamed.
+    Relink {
+        name: String,
+        before: String,
+        after: String,
+    },
+}
+
+impl Operation {
+    fn undo<T: NotesProvider>(&mut self, notes_provider: &T, config: &Config) -> Result<()> {
+        match self {
+            Operation::Create { name, content } => {
+                *content = Some(delete(notes_provider, config, name)?);
+                Ok(())
+            }
+            Operation::Delete { name, content } => {
+                bring_back(notes_provider, config, name, content)
+            }
+            Operation::Rename { from, to } => rename(notes_provider, config, to, from),
+            Operation::Retag {
+                name,
+                before,
+                after,
+            }
+            | Operation::Relink {
+                name,
+                before,
+                after,
+            } => rewrite(notes_provider, config, name, after, before),
+        }
+    }
+
+    fn redo<T: NotesProvider>(&mut self, notes_provider: &T, config: &Config) -> Result<()> {
+        match self {
+            Operation::Create { name, content } => bring_back(
+                notes_provider,
+                config,
+                name,
+                content.as_deref().unwrap_or_default(),
+            ),
+            Operation::Delete { name, content } => {
+                *content = delete(notes_provider, config, name)?;
+                Ok(())
+            }
+            Operation::Rename { from, to } => rename(notes_provider, config, from, to),
+            Operation::Retag {
+                name,
+                before,
+                after,
+            }
+            | Operation::Relink {
+                name,
+                before,
+                after,
+            } => rewrite(notes_provider, config, name, before, after),
+        }
+    }
+
+    fn get_name(&self) -> &str {
+        match self {
+            Operation::Create { name, .. }
+            | Operation::Delete { name, .. }
+            | Operation::Retag { name, .. }
+            | Operation::Relink { name, .. } => name,
+            Operation::Rename { from, .. } => from,
+        }
+    }
+
+    fn get_verb(&self) -> &str {
+        match self {
+            Operation::Create { .. } => "creating",
+            Operation::Delete { .. } => "deleting",
+            Operation::Rename { from, to }
+                if Path::new(from).parent() == Path::new(to).parent() =>
+            {
+                "renaming"
+            }
+            Operation::Rename { .. } => "moving",
+            Operation::Retag { .. } => "retagging",
+            Operation::Relink { .. } => "updating links in",
+        }
+    }
+
+    fn to_toml(&self) -> Table {
+        let mut table = Table::new();
+        let mut insert = |key: &str, value: &str| {
+            table.insert(String::from(key), Value::String(value.to_string()));
+        };
+        match self {
+            Operation::Create { name, content } => {
+                insert("type", "create");
+                insert("name", name);
+                if let Some(content) = content {
+                    insert("content", content);
+                }
+            }
+            Operation::Delete { name, content } => {
+                insert("type", "delete");
+                insert("name", name);
+                insert("content", content);
+            }
+            Operation::Rename { from, to } => {
+                insert("type", "rename");
+                insert("from", from);
+                insert("to", to);
+            }
+            Operation::Retag {
+                name,
+                before,
+                after,
+            } => {
+                insert("type", "retag");
+                insert("name", name);
+                insert("before", before);
+                insert("after", after);
+            }
+            Operation::Relink {
+                name,
+                before,
+                after,
+            } => {
+                insert("type", "relink");
+                insert("name", name);
+                insert("before", before);
+                insert("after", after);
+            }
+        }
+        table
+    }
+
+    fn from_toml(table: &Table) -> Option<Self> {
+        let get = |key: &str| table.get(key)?.as_str().map(String::from);
+        match get("type")?.as_str() {
+            "create" => Some(Operation::Create {
+                name: get("name")?,
+                content: get("content"),
+            }),
+            "delete" => Some(Operation::Delete {
+                name: get("name")?,
+                content: get("content")?,
+            }),
+            "rename" => Some(Operation::Rename {
+                from: get("from")?,
+                to: get("to")?,
+            }),
+            "retag" => Some(Operation::Retag {
+                name: get("name")?,
+                before: get("before")?,
+                after: get("after")?,
+            }),
+            "relink" => Some(Operation::Relink {
+                name: get("name")?,
+                before: get("before")?,
+                after: get("after")?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+// Everything one action did, undone and redone together. Deleting three marked notes is one step.
+type Step = Vec<Operation>;
+
+// The undo and redo stacks. They're journaled to disk after every change, so a restart doesn't
+// lose them.
+pub struct UndoHistory {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+    journal_path: PathBuf,
+    // The notes the last undo or redo renamed or moved, as (from, to).
+    moved: Vec<(String, String)>,
+}
+
+impl UndoHistory {
+    // Pick up the stacks from the journal. A journal that can't be read is only logged, since
+    // losing the history shouldn't stop anyone getting to their notes.
+    pub fn load(config: &Config) -> Self {
+        let mut history = UndoHistory {
+            undo: vec![],
+            redo: vec![],
+            journal_path: PathBuf::from(config.get_undo_journal()),
+            moved: vec![],
+        };
+
+        match fs::read_to_string(&history.journal_path) {
+            Ok(contents) => match contents.parse::<Table>() {
+                Ok(journal) => {
+                    history.undo = read_steps(&journal, "undo");
+                    history.redo = read_steps(&journal, "redo");
+                }
+                Err(error) => warn!("could not parse the undo journal: {}", error),
+            },
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => warn!("could not read the undo journal: {}", error),
+        }
+        history
+    }
+
+    // Remember a step that was just done. Doing something new means the redo stack no longer
+    // applies.
+    pub fn record(&mut self, operations: impl IntoIterator<Item = Operation>) {
+        let step: Step = operations.into_iter().collect();
+        if step.is_empty() {
+            return;
+        }
+        self.undo.push(step);
+        if self.undo.len() > MAX_STEPS {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+        self.save();
+    }
+
+    // Undo the last step, returning a message saying what was undone, or None if there's nothing
+    // to undo.
+    pub fn undo<T: NotesProvider>(
+        &mut self,
+        notes_provider: &T,
+        config: &Config,
+    ) -> Result<Option<String>> {
+        let Some(mut step) = self.undo.pop() else {
+            return Ok(None);
+        };
+
+        // Later operations may depend on earlier ones, so they're undone in reverse.
+        let mut undone = vec![];
+        let mut result = Ok(());
+        while let Some(mut operation) = step.pop() {
+            match operation.undo(notes_provider, config) {
+                Ok(()) => undone.push(operation),
+                Err(error) => {
+                    step.push(operation);
+                    result = Err(error);
+                    break;
+                }
+            }
+        }
+        undone.reverse();
+        self.moved = undone
+            .iter()
+            .filter_map(|operation| match operation {
+                Operation::Rename { from, to } => Some((to.clone(), from.clone())),
+                _ => None,
+            })
+            .collect();
+
+        // Whatever couldn't be undone stays on the stack to try again.
+        let message = describe(&undone);
+        if !undone.is_empty() {
+            self.redo.push(undone);
+        }
+        if !step.is_empty() {
+            self.undo.push(step);
+        }
+        self.save();
+
+        result.context("could not undo")?;
+        Ok(Some(format!("Undid {}", message)))
+    }
+
+    pub fn redo<T: NotesProvider>(
+        &mut self,
+        notes_provider: &T,
+        config: &Config,
+    ) -> Result<Option<String>> {
+        let Some(step) = self.redo.pop() else {
+            return Ok(None);
+        };
+
+        let mut redone = vec![];
+        let mut not_redone = vec![];
+        let mut result = Ok(());
+        let mut operations = step.into_iter();
+        for mut operation in operations.by_ref() {
+            if let Err(error) = operation.redo(notes_provider, config) {
+                not_redone.push(operation);
+                result = Err(error);
+                break;
+            }
+            redone.push(operation);
+        }
+        // The operation that failed goes back on the redo stack with the ones after it.
+        not_redone.extend(operations);
+        self.moved = redone
+            .iter()
+            .filter_map(|operation| match operation {
+                Operation::Rename { from, to } => Some((from.clone(), to.clone())),
+                _ => None,
+            })
+            .collect();
+
+        let message = describe(&redone);
+        if !redone.is_empty() {
+            self.undo.push(redone);
+        }
+        if !not_redone.is_empty() {
+            self.redo.push(not_redone);
+        }
+        self.save();
+
+        result.context("could not redo")?;
+        Ok(Some(format!("Redid {}", message)))
+    }
+
+    // Where the last undo or redo moved notes from and to, for anything that has to follow them.
+    pub fn take_moved(&mut self) -> Vec<(String, String)> {
+        std::mem::take(&mut self.moved)
+    }
+
+    // Forget everything done to a note that's been purged from the trash, so no copy of it is left
+    // in the journal after the user was told it's gone for good. Its earlier names are followed
+    // back through renames. Another deleted note with the same name can still be restored from
+    // the trash, just not by undoing.
+    pub fn forget(&mut self, name: &str) {
+        let mut current = Some(name.to_string());
+        for step in self.undo.iter_mut().rev() {
+            let mut kept = vec![];
+            for operation in step.drain(..).rev() {
+                let Some(name) = current.as_deref() else {
+                    kept.push(operation);
+                    continue;
+                };
+                match &operation {
+                    Operation::Rename { from, to } if to == name => current = Some(from.clone()),
+                    // Before it was created, the name was some other note's.
+                    Operation::Create { name: created, .. } if created == name => current = None,
+                    Operation::Rename { .. } => kept.push(operation),
+                    other if other.get_name() == name => {}
+                    _ => kept.push(operation),
+                }
+            }
+            kept.reverse();
+            *step = kept;
+        }
+        for step in self.redo.iter_mut() {
+            step.retain(|operation| match operation {
+                Operation::Rename { from, to } => from != name && to != name,
+                other => other.get_name() != name,
+            });
+        }
+        self.undo.retain(|step| !step.is_empty());
+        self.redo.retain(|step| !step.is_empty());
+        self.save();
+    }
+
+    fn save(&self) {
+        if let Err(error) = self.write_journal() {
+            warn!("could not write the undo journal: {:#}", error);
+        }
+    }
+
+    fn write_journal(&self) -> Result<()> {
+        let mut journal = Table::new();
+        journal.insert(String::from("undo"), write_steps(&self.undo));
+        journal.insert(String::from("redo"), write_steps(&self.redo));
+
+        // Written to a temporary file first, so a crash can't leave half a journal behind.
+        let directory = self
+            .journal_path
+            .parent()
+            .context("the undo journal has no parent directory")?;
+        fs::create_dir_all(directory)?;
+        let mut file = NamedTempFile::new_in(directory)?;
+        std::io::Write::write_all(&mut file, journal.to_string().as_bytes())?;
+        file.persist(&self.journal_path)?;
+        Ok(())
+    }
+}
+
+// "deleting todo.txt", or "deleting 3 notes" for a step with more than one operation. Links
+// updated along with a rename aren't counted, it's still renaming one note.
+fn describe(step: &[Operation]) -> String {
+    let described: Vec<&Operation> = step
+        .iter()
+        .filter(|operation| !matches!(operation, Operation::Relink { .. }))
+        .collect();
+    match described[..] {
+        [] => match step {
+            [] => String::from("nothing"),
+            [operation] => format!("{} {}", operation.get_verb(), operation.get_name()),
+            _ => format!("updating links in {} notes", step.len()),
+        },
+        [operation] => format!("{} {}", operation.get_verb(), operation.get_name()),
+        [operation, ..] => format!("{} {} notes", operation.get_verb(), described.len()),
+    }
+}
+
+fn read_steps(journal: &Table, key: &str) -> Vec<Step> {
+    let Some(steps) = journal.get(key).and_then(|steps| steps.as_array()) else {
+        return vec![];
+    };
+    steps
+        .iter()
+        .filter_map(|step| {
+            step.as_array()?
+                .iter()
+                .map(|operation| Operation::from_toml(operation.as_table()?))
+                .collect::<Option<Step>>()
+        })
+        .collect()
+}
+
+fn write_steps(steps: &[Step]) -> Value {
+    Value::Array(
+        steps
+            .iter()
+            .map(|step| {
+                Value::Array(
+                    step.iter()
+                        .map(|operation| Value::Table(operation.to_toml()))
+                        .collect(),
+                )
+            })
+            .collect(),
+    )
+}
+
+// Move a note to the trash, returning what was in it.
+fn delete<T: NotesProvider>(notes_provider: &T, config: &Config, name: &str) -> Result<String> {
+    let note = find_note(notes_provider, config, name)?;
+    let content = notes_provider.read_note(&note)?;
+    notes_provider.delete_note(&note)?;
+    Ok(content)
+}
+
+// Bring a deleted note back, from the trash if it's still there so it keeps its history, or from
+// the content saved when it was deleted if it's been purged.
+fn bring_back<T: NotesProvider>(
+    notes_provider: &T,
+    config: &Config,
+    name: &str,
+    content: &str,
+) -> Result<()> {
+    let path = get_new_note_path(config, name)?;
+    if notes_provider.note_exists(&path) {
+        bail!("{} already exists", name);
+    }
+
+    let trash_entry = notes_provider
+        .get_trash()?
+        .into_iter()
+        .filter(|entry| entry.name == name)
+        .max_by_key(|entry| entry.deleted);
+    if let Some(entry) = trash_entry {
+        return notes_provider.restore_note(&entry);
+    }
+
+    let note = notes_provider.create_note(NoteEntry::new(
+        path,
+        name.to_string(),
+        SystemTime::now(),
+        false,
+        0,
+    ))?;
+    notes_provider.write_note(&note, content)
+}
+
+fn rename<T: NotesProvider>(
+    notes_provider: &T,
+    config: &Config,
+    from: &str,
+    to: &str,
+) -> Result<()> {
+    let new_path = get_new_note_path(config, to)?;
+    if notes_provider.note_exists(&new_path) {
+        bail!("{} already exists", to);
+    }
+    let note = find_note(notes_provider, config, from)?;
+    rename_keeping_pin(notes_provider, config, &note, &new_path)
+}
+
+// Put back a note's content from before a change, as long as it hasn't been edited since.
+fn rewrite<T: NotesProvider>(
+    notes_provider: &T,
+    config: &Config,
+    name: &str,
+    expected: &str,
+    content: &str,
+) -> Result<()> {
+    let note = find_note(notes_provider, config, name)?;
+    if notes_provider.read_note(&note)? != expected {
+        bail!("{} has been edited since", name);
+    }
+    notes_provider.write_note(&note, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(undo: Vec<Step>, redo: Vec<Step>) -> (UndoHistory, tempfile::TempDir) {
+        let directory = tempfile::tempdir().expect("temp dir should be created");
+        let history = UndoHistory {
+            undo,
+            redo,
+            journal_path: directory.path().join("journal.toml"),
+            moved: vec![],
+        };
+        (history, directory)
+    }
+
+    fn rename(from: &str, to: &str) -> Operation {
+        Operation::Rename {
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+
+    fn delete(name: &str) -> Operation {
+        Operation::Delete {
+            name: name.to_string(),
+            content: String::from("secret"),
+        }
+    }
+
+    fn retag(name: &str) -> Operation {
+        Operation::Retag {
+            name: name.to_string(),
+            before: String::from("secret"),
+            after: String::from("secret, tagged"),
+        }
+    }
+
+    #[test]
+    fn forget_follows_a_purged_note_back_through_renames() {
+        let (mut history, _directory) = history(
+            vec![
+                vec![retag("old.md"), retag("other.md")],
+                vec![rename("old.md", "new.md")],
+                vec![retag("new.md")],
+                vec![delete("new.md"), delete("other.md")],
+            ],
+            vec![],
+        );
+        history.forget("new.md");
+        let journal = fs::read_to_string(&history.journal_path).expect("journal should be saved");
+        assert!(!journal.contains("old.md"));
+        assert!(!journal.contains("new.md"));
+        assert_eq!(history.undo.len(), 2);
+        assert_eq!(describe(&history.undo[0]), "retagging other.md");
+        assert_eq!(describe(&history.undo[1]), "deleting other.md");
+    }
+
+    #[test]
+    fn forget_stops_at_the_note_being_created() {
+        let (mut history, _directory) = history(
+            vec![
+                vec![retag("note.md")],
+                vec![Operation::Create {
+                    name: String::from("note.md"),
+                    content: None,
+                }],
+                vec![delete("note.md")],
+            ],
+            vec![vec![retag("note.md")], vec![retag("other.md")]],
+        );
+        history.forget("note.md");
+        // The first retag was of an older note that had the same name.
+        assert_eq!(history.undo.len(), 1);
+        assert_eq!(describe(&history.undo[0]), "retagging note.md");
+        assert_eq!(history.redo.len(), 1);
+        assert_eq!(describe(&history.redo[0]), "retagging other.md");
+    }
+}